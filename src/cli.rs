@@ -0,0 +1,552 @@
+//! Native command line interface, to try settings against workloads without
+//! a cluster, kwctl or a wasm build. Available with the `cli` feature.
+
+use crate::{
+    convert, evaluate,
+    manifest::{self, ManifestOptions, Mode},
+    settings::Settings,
+    simulate, suggest, PolicyResponse,
+};
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use kubewarden::{request::KubernetesAdmissionRequest, settings::Validatable};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Evaluates the allowed-fsgroups-psp policy offline")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluates AdmissionReviews, AdmissionRequests or Pods against the settings
+    Evaluate(EvaluateArgs),
+    /// Evaluates the pods and workloads of a cluster dump, like the output of
+    /// `kubectl get pods,deployments -A -o json`, and reports the outcome per
+    /// namespace
+    Simulate(SimulateArgs),
+    /// Suggests settings admitting every pod and workload of a cluster dump
+    Suggest(SuggestArgs),
+    /// Converts PodSecurityPolicies, Gatekeeper K8sPSPFSGroup constraints and
+    /// OpenShift SecurityContextConstraints into settings, reporting the
+    /// fields that have no equivalent
+    Convert(ConvertArgs),
+    /// Generates the ClusterAdmissionPolicy, or AdmissionPolicy, running the
+    /// policy with the settings
+    Manifest(Box<ManifestArgs>),
+}
+
+#[derive(Args)]
+struct EvaluateArgs {
+    /// Settings of the policy, in YAML or JSON
+    #[arg(short, long)]
+    settings: PathBuf,
+    /// Files with the documents to evaluate, in YAML or JSON, with several
+    /// YAML documents per file allowed. Reads stdin when none is given, or
+    /// for `-`.
+    documents: Vec<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    /// Settings of the policy, in YAML or JSON
+    #[arg(short, long)]
+    settings: PathBuf,
+    /// Files with the objects of the cluster, as Lists or single objects, in
+    /// YAML or JSON. Reads stdin when none is given, or for `-`.
+    documents: Vec<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = ReportFormat::Table)]
+    output: ReportFormat,
+}
+
+#[derive(Args)]
+struct SuggestArgs {
+    /// Files with the objects of the cluster, as Lists or single objects, in
+    /// YAML or JSON. Reads stdin when none is given, or for `-`.
+    documents: Vec<PathBuf>,
+    /// Add a profile for each namespace, matching its service accounts
+    #[arg(long)]
+    per_namespace: bool,
+    /// Merge ranges separated by at most this number of GIDs
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i64).range(0..))]
+    gap: i64,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// Files with the resources to convert, in YAML or JSON, with several
+    /// YAML documents per file allowed. Reads stdin when none is given, or
+    /// for `-`.
+    documents: Vec<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = ConvertOutput::Manifest)]
+    output: ConvertOutput,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConvertOutput {
+    /// ClusterAdmissionPolicies enforcing the settings
+    Manifest,
+    /// Settings only
+    Settings,
+}
+
+#[derive(Args)]
+struct ManifestArgs {
+    /// Settings of the policy, in YAML or JSON
+    #[arg(short, long)]
+    settings: PathBuf,
+    /// Name of the policy
+    #[arg(long, default_value = "allowed-fsgroups-psp")]
+    name: String,
+    /// Generate an AdmissionPolicy in the namespace, instead of a
+    /// ClusterAdmissionPolicy
+    #[arg(short, long)]
+    namespace: Option<String>,
+    /// Label selector of the namespaces the ClusterAdmissionPolicy applies
+    /// to, in YAML or JSON, like `{matchLabels: {team: shop}}`
+    #[arg(long, value_parser = parse_selector)]
+    namespace_selector: Option<Value>,
+    /// Label selector of the pods the policy applies to, in YAML or JSON
+    #[arg(long, value_parser = parse_selector)]
+    object_selector: Option<Value>,
+    /// Mode of the policy, defaults to the mode of the policy server
+    #[arg(long, value_enum)]
+    mode: Option<Mode>,
+    /// Policy server running the policy, defaults to `default`
+    #[arg(long)]
+    policy_server: Option<String>,
+    /// URL of the policy module, defaults to the release of this version
+    #[arg(long)]
+    module: Option<String>,
+}
+
+fn parse_selector(selector: &str) -> Result<Value> {
+    let selector: Value = serde_yaml::from_str(selector)?;
+    if !selector.is_object() {
+        return Err(anyhow!("expected a label selector"));
+    }
+    Ok(selector)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Runs the command line interface, exiting with 1 when a document is
+/// rejected, and with 2 on errors
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Evaluate(args) => run_evaluate(args),
+        Command::Simulate(args) => run_simulate(args),
+        Command::Suggest(args) => run_suggest(args),
+        Command::Convert(args) => run_convert(args),
+        Command::Manifest(args) => run_manifest(*args),
+    };
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {error:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run_evaluate(args: EvaluateArgs) -> Result<ExitCode> {
+    let settings = read_settings(&args.settings)?;
+    let evaluations = read_documents(&args.documents)?
+        .iter()
+        .map(|document| evaluate_document(document, &settings))
+        .collect::<Result<Vec<_>>>()?;
+
+    match args.output {
+        Output::Text => {
+            for evaluation in &evaluations {
+                print!("{}", evaluation.to_text());
+            }
+        }
+        Output::Json => println!("{}", serde_json::to_string_pretty(&evaluations)?),
+    }
+
+    let rejected = evaluations
+        .iter()
+        .any(|evaluation| evaluation.decision == Decision::Rejected);
+    Ok(if rejected {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn run_simulate(args: SimulateArgs) -> Result<ExitCode> {
+    let settings = read_settings(&args.settings)?;
+    let documents = read_documents(&args.documents)?
+        .into_iter()
+        .map(|document| document.value)
+        .collect();
+    let reports = simulate::simulate(&simulate::items(documents), &settings)?;
+
+    match args.output {
+        ReportFormat::Table => print!("{}", simulate::to_table(&reports)),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+        ReportFormat::Csv => print!("{}", simulate::to_csv(&reports)),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_suggest(args: SuggestArgs) -> Result<ExitCode> {
+    let documents = read_documents(&args.documents)?
+        .into_iter()
+        .map(|document| document.value)
+        .collect();
+    let scope = if args.per_namespace {
+        suggest::Scope::Namespace
+    } else {
+        suggest::Scope::Global
+    };
+    let settings = suggest::suggest(&simulate::items(documents), scope, args.gap)?;
+    print!("{}", serde_yaml::to_string(&settings)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_convert(args: ConvertArgs) -> Result<ExitCode> {
+    let mut output = Vec::new();
+    for document in read_documents(&args.documents)? {
+        let conversion =
+            convert::convert(&document.value).with_context(|| document.source.clone())?;
+        for field in &conversion.unmapped {
+            eprintln!(
+                "warning: {}: {field} has no equivalent, it is left out",
+                conversion.source
+            );
+        }
+        let value = match args.output {
+            ConvertOutput::Manifest => &conversion.manifest,
+            ConvertOutput::Settings => &conversion.settings,
+        };
+        output.push(format!(
+            "# {}\n{}",
+            conversion.source,
+            serde_yaml::to_string(value)?
+        ));
+    }
+    print!("{}", output.join("---\n"));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_manifest(args: ManifestArgs) -> Result<ExitCode> {
+    let settings = read_settings(&args.settings)?;
+    let options = ManifestOptions {
+        name: args.name,
+        namespace: args.namespace,
+        namespace_selector: args.namespace_selector,
+        object_selector: args.object_selector,
+        mode: args.mode,
+        policy_server: args.policy_server,
+        module: args.module,
+    };
+    print!(
+        "{}",
+        serde_yaml::to_string(&manifest::manifest(&settings, &options)?)?
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reads and validates the settings, printing the range warnings that the
+/// policy would log
+pub(crate) fn read_settings(path: &Path) -> Result<Settings> {
+    let settings: Value = serde_yaml::from_slice(&read(path)?)
+        .with_context(|| format!("cannot parse {}", path.display()))?;
+    let settings = Settings::from_slice(&serde_json::to_vec(&settings)?)
+        .map_err(|error| anyhow!("invalid settings: {error}"))?;
+    settings
+        .validate()
+        .map_err(|error| anyhow!("invalid settings: {error}"))?;
+    if !settings.strict_ranges {
+        for warning in settings.range_warnings() {
+            eprintln!("warning: {warning}");
+        }
+    }
+    Ok(settings)
+}
+
+/// A document read from a file, along with where it comes from
+pub(crate) struct Document {
+    pub source: String,
+    pub value: Value,
+}
+
+/// Reads every YAML or JSON document of the files, or of stdin when there is
+/// none
+pub(crate) fn read_documents(paths: &[PathBuf]) -> Result<Vec<Document>> {
+    let stdin = [PathBuf::from("-")];
+    let paths = if paths.is_empty() { &stdin[..] } else { paths };
+
+    let mut documents = Vec::new();
+    for path in paths {
+        let values = serde_yaml::Deserializer::from_slice(&read(path)?)
+            .map(Value::deserialize)
+            .filter(|value| !matches!(value, Ok(Value::Null)))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("cannot parse {}", path.display()))?;
+        let count = values.len();
+        let name = if path == Path::new("-") {
+            "stdin".to_string()
+        } else {
+            path.display().to_string()
+        };
+        documents.extend(values.into_iter().enumerate().map(|(index, value)| {
+            let source = if count == 1 {
+                name.clone()
+            } else {
+                format!("{name}#{}", index + 1)
+            };
+            Document { source, value }
+        }));
+    }
+    Ok(documents)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_end(&mut contents)?;
+    } else {
+        contents = fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+    }
+    Ok(contents)
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum Decision {
+    Accepted,
+    Rejected,
+    Mutated,
+}
+
+#[derive(Serialize, Debug)]
+struct Evaluation {
+    source: String,
+    namespace: String,
+    name: String,
+    decision: Decision,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patch: Option<json_patch::Patch>,
+}
+
+impl Evaluation {
+    fn to_text(&self) -> String {
+        let mut text = format!("{}: ", self.source);
+        if !self.namespace.is_empty() {
+            text.push_str(&format!("{}/", self.namespace));
+        }
+        text.push_str(&self.name);
+        match self.decision {
+            Decision::Accepted => text.push_str(": accepted\n"),
+            Decision::Rejected => text.push_str(&format!(
+                ": rejected: {}\n",
+                self.message.as_deref().unwrap_or_default()
+            )),
+            Decision::Mutated => text.push_str(": mutated\n"),
+        }
+        for warning in &self.warnings {
+            text.push_str(&format!("  warning: {warning}\n"));
+        }
+        if let Some(patch) = &self.patch {
+            text.push_str(&format!(
+                "  patch: {}\n",
+                serde_json::to_string(patch).unwrap_or_default()
+            ));
+        }
+        text
+    }
+}
+
+/// Builds the admission request out of an AdmissionReview, a bare
+/// AdmissionRequest, as used by kwctl, or a Pod, which is evaluated as if it
+/// was being created
+pub(crate) fn admission_request(document: &Value) -> Result<KubernetesAdmissionRequest> {
+    let request = match &document["kind"] {
+        Value::String(kind) if kind == "AdmissionReview" => document["request"].clone(),
+        Value::String(kind) if kind == "Pod" => {
+            return Ok(KubernetesAdmissionRequest {
+                operation: "CREATE".to_string(),
+                namespace: document["metadata"]["namespace"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                name: document["metadata"]["name"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                object: document.clone(),
+                ..KubernetesAdmissionRequest::default()
+            });
+        }
+        Value::Object(_) if document["object"].is_object() => document.clone(),
+        Value::String(kind) => return Err(anyhow!("unsupported kind {kind}")),
+        _ => {
+            return Err(anyhow!(
+                "expected an AdmissionReview, an AdmissionRequest or a Pod"
+            ))
+        }
+    };
+    Ok(serde_json::from_value(request)?)
+}
+
+fn evaluate_document(document: &Document, settings: &Settings) -> Result<Evaluation> {
+    let request = admission_request(&document.value)
+        .with_context(|| format!("cannot evaluate {}", document.source))?;
+    let metadata = &request.object["metadata"];
+    let name = [&metadata["name"], &metadata["generateName"]]
+        .iter()
+        .find_map(|name| name.as_str())
+        .unwrap_or(&request.name)
+        .to_string();
+
+    let mut evaluation = Evaluation {
+        source: document.source.clone(),
+        namespace: metadata["namespace"]
+            .as_str()
+            .unwrap_or(&request.namespace)
+            .to_string(),
+        name,
+        decision: Decision::Accepted,
+        message: None,
+        warnings: Vec::new(),
+        patch: None,
+    };
+    match evaluate(&request, settings.clone())? {
+        PolicyResponse::Accept => {}
+        PolicyResponse::AcceptWithWarnings(warnings) => evaluation.warnings = warnings,
+        PolicyResponse::Reject(message) => {
+            evaluation.decision = Decision::Rejected;
+            evaluation.message = Some(message);
+        }
+        PolicyResponse::Mutate(mutated_object) => {
+            evaluation.decision = Decision::Mutated;
+            evaluation.patch = Some(json_patch::diff(&request.object, &mutated_object));
+        }
+        PolicyResponse::MutateWithWarnings(mutated_object, warnings) => {
+            evaluation.decision = Decision::Mutated;
+            evaluation.patch = Some(json_patch::diff(&request.object, &mutated_object));
+            evaluation.warnings = warnings;
+        }
+    }
+    Ok(evaluation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(settings: &str) -> Settings {
+        Settings::from_slice(settings.as_bytes()).unwrap()
+    }
+
+    fn document(value: Value) -> Document {
+        Document {
+            source: "pods.yaml".to_string(),
+            value,
+        }
+    }
+
+    fn pod(fs_group: Option<i64>) -> Value {
+        let mut pod = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": "nginx", "namespace": "web"},
+            "spec": {"containers": [{"name": "nginx", "image": "nginx"}]},
+        });
+        if let Some(fs_group) = fs_group {
+            pod["spec"]["securityContext"] = serde_json::json!({ "fsGroup": fs_group });
+        }
+        pod
+    }
+
+    #[test]
+    fn evaluate_pods() -> Result<()> {
+        let settings = settings(r#"{"rule": "MustRunAs", "ranges": ["1000-2000"]}"#);
+
+        let evaluation = evaluate_document(&document(pod(Some(1500))), &settings)?;
+        assert_eq!(evaluation.decision, Decision::Accepted);
+        assert_eq!(evaluation.to_text(), "pods.yaml: web/nginx: accepted\n");
+
+        let evaluation = evaluate_document(&document(pod(Some(3000))), &settings)?;
+        assert_eq!(
+            evaluation.to_text(),
+            "pods.yaml: web/nginx: rejected: fsGroup 3000 is not included in any range\n"
+        );
+
+        let evaluation = evaluate_document(&document(pod(None)), &settings)?;
+        assert_eq!(evaluation.decision, Decision::Mutated);
+        assert_eq!(
+            serde_json::to_value(&evaluation.patch)?,
+            serde_json::json!([
+                {"op": "add", "path": "/spec/securityContext", "value": {"fsGroup": 1000}},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_admission_requests() -> Result<()> {
+        let settings = settings(r#"{"rule": "MayRunAs", "ranges": ["1000-2000"]}"#);
+        let request: Value = serde_json::from_str(include_str!("../test_data/pod_creation.json"))?;
+
+        let evaluation = evaluate_document(&document(request.clone()), &settings)?;
+        assert_eq!(evaluation.to_text(), "pods.yaml: nginx: accepted\n");
+
+        let review = serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": request,
+        });
+        let evaluation = evaluate_document(&document(review), &settings)?;
+        assert_eq!(evaluation.decision, Decision::Accepted);
+
+        let ingress: Value =
+            serde_json::from_str(include_str!("../test_data/ingress_creation.json"))?;
+        assert_eq!(
+            evaluate_document(&document(ingress), &settings)?.decision,
+            Decision::Accepted
+        );
+
+        assert_eq!(
+            evaluate_document(
+                &document(serde_json::json!({"kind": "Deployment"})),
+                &settings
+            )
+            .unwrap_err()
+            .root_cause()
+            .to_string(),
+            "unsupported kind Deployment"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,715 @@
+//! Converts the resources of other pod security admission mechanisms into
+//! settings of the policy, along with the policy manifest enforcing them.
+//! Available with the `convert` feature.
+
+use crate::{
+    manifest::{self, ManifestOptions, Mode},
+    settings::{ApiVersion, Range, Ranges, Rule, Settings},
+};
+use anyhow::{anyhow, Context, Result};
+use kubewarden::settings::Validatable;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Annotations of PodSecurityPolicies configuring other security contexts
+/// than the fsGroup
+const PSP_ANNOTATIONS: [&str; 2] = [
+    "seccomp.security.alpha.kubernetes.io/",
+    "apparmor.security.beta.kubernetes.io/",
+];
+
+/// Namespace annotation with the GIDs that OpenShift assigns to the pods of
+/// the namespace
+const SCC_SUPPLEMENTAL_GROUPS_ANNOTATION: &str = "openshift.io/sa.scc.supplemental-groups";
+
+/// Label set by Kubernetes on every namespace, with its name
+const NAMESPACE_NAME_LABEL: &str = "kubernetes.io/metadata.name";
+
+/// Settings equivalent to a resource
+#[derive(Debug)]
+pub struct Conversion {
+    /// Kind and name of the converted resource, like
+    /// `PodSecurityPolicy/restricted`
+    pub source: String,
+    pub settings: Value,
+    /// ClusterAdmissionPolicy enforcing the settings, named after the
+    /// resource
+    pub manifest: Value,
+    /// Fields of the resource that have no equivalent in the settings, and
+    /// are left out
+    pub unmapped: Vec<String>,
+}
+
+/// Converts every document of the YAML, or JSON, stream
+pub fn convert_yaml(yaml: &str) -> Result<Vec<Conversion>> {
+    serde_yaml::Deserializer::from_str(yaml)
+        .map(Value::deserialize)
+        .filter(|document| !matches!(document, Ok(Value::Null)))
+        .map(|document| convert(&document?))
+        .collect()
+}
+
+/// Outcome of the conversion of a resource, before the settings are
+/// validated
+struct Converted {
+    settings: Settings,
+    /// Options of the manifest, other than its name, like its selectors
+    options: ManifestOptions,
+    unmapped: Vec<String>,
+}
+
+impl Converted {
+    fn new(fs_group: Rule) -> Converted {
+        Converted {
+            settings: Settings {
+                api_version: ApiVersion::V2,
+                fs_group,
+                ..Settings::default()
+            },
+            options: ManifestOptions::default(),
+            unmapped: Vec::new(),
+        }
+    }
+}
+
+/// Converts a resource, which can be a PodSecurityPolicy, a `K8sPSPFSGroup`
+/// constraint of OPA Gatekeeper, or an OpenShift SecurityContextConstraints
+pub fn convert(document: &Value) -> Result<Conversion> {
+    let kind = document["kind"].as_str().unwrap_or_default();
+    let name = document["metadata"]["name"].as_str().unwrap_or_default();
+    let source = format!("{kind}/{name}");
+    let converted = match kind {
+        "PodSecurityPolicy" => psp(document),
+        "K8sPSPFSGroup" => gatekeeper(document),
+        "SecurityContextConstraints" => scc(document),
+        "" => Err(anyhow!("missing kind")),
+        _ => Err(anyhow!("unsupported kind")),
+    }
+    .with_context(|| format!("cannot convert {source}"))?;
+
+    let settings = converted.settings;
+    settings
+        .validate()
+        .map_err(|error| anyhow!("cannot convert {source}: invalid settings: {error}"))?;
+    let options = ManifestOptions {
+        name: name.to_string(),
+        ..converted.options
+    };
+    Ok(Conversion {
+        manifest: manifest::manifest(&settings, &options)?,
+        settings: serde_json::to_value(&settings)?,
+        source,
+        unmapped: converted.unmapped,
+    })
+}
+
+/// `fsGroup` strategy as PodSecurityPolicies write it, which Gatekeeper
+/// constraints and SecurityContextConstraints follow. The extensions of the
+/// settings, like `MustNotRunAs`, single `values`, or string and open-ended
+/// ranges, are not part of it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PspRule {
+    rule: PspRuleKind,
+    #[serde(default)]
+    ranges: Option<Vec<PspRange>>,
+}
+
+#[derive(Deserialize)]
+enum PspRuleKind {
+    MustRunAs,
+    MayRunAs,
+    RunAsAny,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, expecting = "a range with min and max")]
+struct PspRange {
+    min: i64,
+    max: i64,
+}
+
+/// Deserializes a rule written as in PodSecurityPolicies, with `rule` and
+/// `ranges`
+fn rule(value: &Value, path: &str) -> Result<Rule> {
+    let psp_rule: PspRule = serde_path_to_error::deserialize(value).map_err(|error| {
+        let field = error.path().to_string();
+        let path = match field.as_str() {
+            "." => path.to_string(),
+            _ => format!("{path}.{field}"),
+        };
+        anyhow!("{path}: {}", error.inner())
+    })?;
+    let ranges = || {
+        Ranges::new(
+            psp_rule
+                .ranges
+                .iter()
+                .flatten()
+                .map(|range| Range {
+                    min: range.min,
+                    max: range.max,
+                })
+                .collect(),
+        )
+    };
+    Ok(match psp_rule.rule {
+        PspRuleKind::MustRunAs => Rule::MustRunAs(ranges()),
+        PspRuleKind::MayRunAs => Rule::MayRunAs(ranges()),
+        PspRuleKind::RunAsAny => Rule::RunAsAny,
+    })
+}
+
+/// `spec.fsGroup` of a policy/v1beta1 PodSecurityPolicy maps to the rule of
+/// the settings. Every other field is about another part of the security
+/// context.
+fn psp(psp: &Value) -> Result<Converted> {
+    let spec = psp["spec"]
+        .as_object()
+        .ok_or_else(|| anyhow!("spec: is required"))?;
+    let fs_group = spec
+        .get("fsGroup")
+        .ok_or_else(|| anyhow!("spec.fsGroup: is required"))?;
+    let mut converted = Converted::new(rule(fs_group, "spec.fsGroup")?);
+
+    converted.unmapped = spec
+        .keys()
+        .filter(|field| *field != "fsGroup")
+        .map(|field| format!("spec.{field}"))
+        .collect();
+    converted.unmapped.extend(
+        psp["metadata"]["annotations"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(annotation, _)| annotation)
+            .filter(|annotation| {
+                PSP_ANNOTATIONS
+                    .iter()
+                    .any(|prefix| annotation.starts_with(prefix))
+            })
+            .map(|annotation| format!("metadata.annotations.{annotation}")),
+    );
+    Ok(converted)
+}
+
+/// The `fsGroup` strategy of OpenShift SecurityContextConstraints has a
+/// `type`, `MustRunAs` or `RunAsAny`, instead of a `rule`. Every other field
+/// is about another part of the security context, or about who can use the
+/// constraints.
+fn scc(scc: &Value) -> Result<Converted> {
+    let fs_group = scc
+        .get("fsGroup")
+        .ok_or_else(|| anyhow!("fsGroup: is required"))?;
+    let strategy = fs_group["type"]
+        .as_str()
+        .ok_or_else(|| anyhow!("fsGroup.type: is required"))?;
+    if !["MustRunAs", "RunAsAny"].contains(&strategy) {
+        return Err(anyhow!(
+            "fsGroup.type: unknown strategy `{strategy}`, expected `MustRunAs` or `RunAsAny`"
+        ));
+    }
+    let ranges = &fs_group["ranges"];
+    if strategy == "MustRunAs" && ranges.as_array().is_none_or(Vec::is_empty) {
+        // OpenShift takes the ranges from an annotation of the namespace,
+        // which is out of reach of a policy that is not context aware, and
+        // any rule the policy could apply instead would be weaker
+        return Err(anyhow!(
+            "fsGroup.ranges: no equivalent for the ranges of the \
+             {SCC_SUPPLEMENTAL_GROUPS_ANNOTATION} namespace annotation, pass the ranges \
+             explicitly"
+        ));
+    }
+
+    let mut rule_spec = serde_json::json!({ "rule": strategy });
+    if !ranges.is_null() {
+        rule_spec["ranges"] = ranges.clone();
+    }
+    let mut converted = Converted::new(rule(&rule_spec, "fsGroup")?);
+
+    converted.unmapped.extend(
+        scc.as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(field, value)| {
+                !value.is_null()
+                    && !["apiVersion", "kind", "metadata", "fsGroup"].contains(&field.as_str())
+            })
+            .map(|(field, _)| field.clone()),
+    );
+    Ok(converted)
+}
+
+/// The parameters of a `K8sPSPFSGroup` constraint, from the Gatekeeper
+/// library, use the same rules and ranges as PodSecurityPolicies. The namespaces
+/// and objects it matches are turned into the selectors of the manifest, and
+/// its enforcement action into the mode of the policy.
+fn gatekeeper(constraint: &Value) -> Result<Converted> {
+    let spec = &constraint["spec"];
+    let parameters = &spec["parameters"];
+    if parameters["rule"].is_null() {
+        return Err(anyhow!("spec.parameters.rule: is required"));
+    }
+    let mut converted = Converted::new(rule(parameters, "spec.parameters")?);
+
+    match spec["enforcementAction"].as_str() {
+        None | Some("deny") => {}
+        Some("dryrun") | Some("warn") => {
+            converted.options.mode = Some(Mode::Monitor);
+        }
+        Some(_) => converted
+            .unmapped
+            .push("spec.enforcementAction".to_string()),
+    }
+
+    let selector = &spec["match"];
+    let mut expressions = Vec::new();
+    for (field, operator) in [("namespaces", "In"), ("excludedNamespaces", "NotIn")] {
+        let (names, wildcards): (Vec<&str>, Vec<&str>) = selector[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .partition(|namespace| !namespace.contains('*'));
+        // Label selectors cannot match the prefixes and suffixes that
+        // Gatekeeper allows
+        converted.unmapped.extend(
+            wildcards
+                .iter()
+                .map(|namespace| format!("spec.match.{field}: {namespace}")),
+        );
+        if !names.is_empty() {
+            expressions.push(serde_json::json!({
+                "key": NAMESPACE_NAME_LABEL,
+                "operator": operator,
+                "values": names,
+            }));
+        }
+    }
+    let mut namespace_selector = selector["namespaceSelector"].clone();
+    if !expressions.is_empty() {
+        if !namespace_selector.is_object() {
+            namespace_selector = serde_json::json!({});
+        }
+        let existing = namespace_selector["matchExpressions"].take();
+        namespace_selector["matchExpressions"] = existing
+            .as_array()
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(expressions)
+            .collect();
+    }
+    converted.options.namespace_selector =
+        Some(namespace_selector).filter(|selector| !selector.is_null());
+    converted.options.object_selector =
+        Some(selector["labelSelector"].clone()).filter(|selector| !selector.is_null());
+
+    let pods_only = selector["kinds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|kinds| kinds["kinds"].as_array().into_iter().flatten())
+        .all(|kind| kind == "Pod");
+    if !pods_only {
+        converted.unmapped.push("spec.match.kinds".to_string());
+    }
+    for field in ["scope", "name"] {
+        if !selector[field].is_null() {
+            converted.unmapped.push(format!("spec.match.{field}"));
+        }
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PolicyResponse;
+    use kubewarden::request::KubernetesAdmissionRequest;
+
+    const RESTRICTED: &str = r#"
+apiVersion: policy/v1beta1
+kind: PodSecurityPolicy
+metadata:
+  name: restricted
+  annotations:
+    seccomp.security.alpha.kubernetes.io/allowedProfileNames: docker/default,runtime/default
+    kubernetes.io/description: Restricted access
+spec:
+  privileged: false
+  allowPrivilegeEscalation: false
+  requiredDropCapabilities:
+    - ALL
+  volumes:
+    - configMap
+    - secret
+  runAsUser:
+    rule: MustRunAsNonRoot
+  seLinux:
+    rule: RunAsAny
+  supplementalGroups:
+    rule: MustRunAs
+    ranges:
+      - min: 1
+        max: 65535
+  fsGroup:
+    rule: MustRunAs
+    ranges:
+      - min: 1
+        max: 65535
+  readOnlyRootFilesystem: false
+---
+apiVersion: policy/v1beta1
+kind: PodSecurityPolicy
+metadata:
+  name: privileged
+spec:
+  privileged: true
+  fsGroup:
+    rule: RunAsAny
+"#;
+
+    #[test]
+    fn convert_pod_security_policies() -> Result<()> {
+        let conversions = convert_yaml(RESTRICTED)?;
+        assert_eq!(conversions.len(), 2);
+
+        let restricted = &conversions[0];
+        assert_eq!(restricted.source, "PodSecurityPolicy/restricted");
+        assert_eq!(
+            restricted.settings,
+            serde_json::json!({
+                "apiVersion": "v2",
+                "fsGroup": {"rule": "MustRunAs", "ranges": [{"min": 1, "max": 65535}]},
+                "mirrorPods": "Validate",
+            })
+        );
+        assert_eq!(
+            restricted.unmapped,
+            vec![
+                "spec.privileged",
+                "spec.allowPrivilegeEscalation",
+                "spec.requiredDropCapabilities",
+                "spec.volumes",
+                "spec.runAsUser",
+                "spec.seLinux",
+                "spec.supplementalGroups",
+                "spec.readOnlyRootFilesystem",
+                "metadata.annotations.seccomp.security.alpha.kubernetes.io/allowedProfileNames",
+            ]
+        );
+        assert_eq!(
+            restricted.manifest,
+            serde_json::json!({
+                "apiVersion": "policies.kubewarden.io/v1",
+                "kind": "ClusterAdmissionPolicy",
+                "metadata": {"name": "restricted"},
+                "spec": {
+                    "module": concat!(
+                        "registry://ghcr.io/kubewarden/policies/allowed-fsgroups-psp:v",
+                        env!("CARGO_PKG_VERSION")
+                    ),
+                    "rules": [
+                        {
+                            "apiGroups": [""],
+                            "apiVersions": ["v1"],
+                            "resources": ["pods"],
+                            "operations": ["CREATE", "UPDATE"],
+                        },
+                        {
+                            "apiGroups": [""],
+                            "apiVersions": ["v1"],
+                            "resources": ["pods/ephemeralcontainers"],
+                            "operations": ["UPDATE"],
+                        },
+                    ],
+                    "mutating": true,
+                    "settings": restricted.settings,
+                },
+            })
+        );
+
+        let privileged = &conversions[1];
+        assert_eq!(
+            privileged.settings["fsGroup"],
+            serde_json::json!({"rule": "RunAsAny"})
+        );
+        assert_eq!(privileged.manifest["spec"]["mutating"], false);
+        assert_eq!(privileged.unmapped, vec!["spec.privileged"]);
+        Ok(())
+    }
+
+    /// Samples of the `psp-fsgroup` policy of the Gatekeeper library
+    const PSP_FSGROUP: &str = r#"
+apiVersion: constraints.gatekeeper.sh/v1beta1
+kind: K8sPSPFSGroup
+metadata:
+  name: psp-fsgroup
+spec:
+  match:
+    kinds:
+      - apiGroups: [""]
+        kinds: ["Pod"]
+  parameters:
+    rule: "MayRunAs" #"MustRunAs" #"MayRunAs", "RunAsAny"
+    ranges:
+    - min: 1
+      max: 1000
+"#;
+
+    fn gatekeeper_pod(name: &str, fs_group: i64) -> Value {
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": name},
+            "spec": {
+                "securityContext": {"fsGroup": fs_group},
+                "volumes": [{"name": "fsgroup-demo-vol", "emptyDir": {}}],
+                "containers": [{
+                    "name": "fsgroup-demo",
+                    "image": "busybox",
+                    "command": ["sh", "-c", "sleep 1h"],
+                    "volumeMounts": [{"name": "fsgroup-demo-vol", "mountPath": "/data/demo"}],
+                }],
+            },
+        })
+    }
+
+    #[test]
+    fn convert_gatekeeper_constraints() -> Result<()> {
+        let conversion = convert_yaml(PSP_FSGROUP)?.remove(0);
+        assert_eq!(conversion.source, "K8sPSPFSGroup/psp-fsgroup");
+        assert_eq!(
+            conversion.settings,
+            serde_json::json!({
+                "apiVersion": "v2",
+                "fsGroup": {"rule": "MayRunAs", "ranges": [{"min": 1, "max": 1000}]},
+                "mirrorPods": "Validate",
+            })
+        );
+        assert_eq!(conversion.manifest["metadata"]["name"], "psp-fsgroup");
+        assert_eq!(conversion.manifest["spec"]["mutating"], false);
+        assert!(conversion.unmapped.is_empty());
+
+        let settings = Settings::from_slice(&serde_json::to_vec(&conversion.settings)?).unwrap();
+        let evaluate = |pod: Value| {
+            let request = KubernetesAdmissionRequest {
+                operation: "CREATE".to_string(),
+                object: pod,
+                ..KubernetesAdmissionRequest::default()
+            };
+            crate::evaluate(&request, settings.clone()).unwrap()
+        };
+        assert_eq!(
+            evaluate(gatekeeper_pod("fsgroup-allowed", 500)),
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            evaluate(gatekeeper_pod("fsgroup-disallowed", 2000)),
+            PolicyResponse::Reject("fsGroup 2000 is not included in any range".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn convert_gatekeeper_matches() -> Result<()> {
+        let conversion = convert_yaml(
+            r#"
+apiVersion: constraints.gatekeeper.sh/v1beta1
+kind: K8sPSPFSGroup
+metadata:
+  name: psp-fsgroup
+spec:
+  enforcementAction: dryrun
+  match:
+    kinds:
+      - apiGroups: [""]
+        kinds: ["Pod"]
+      - apiGroups: ["apps"]
+        kinds: ["Deployment"]
+    excludedNamespaces: ["kube-system", "gatekeeper-system", "kube-*"]
+    namespaceSelector:
+      matchLabels:
+        team: shop
+    labelSelector:
+      matchExpressions:
+        - {key: app, operator: Exists}
+  parameters:
+    rule: MustRunAs
+    ranges:
+    - min: 1000
+      max: 2000
+"#,
+        )?
+        .remove(0);
+
+        let spec = &conversion.manifest["spec"];
+        assert_eq!(spec["mutating"], true);
+        assert_eq!(spec["mode"], "monitor");
+        assert_eq!(
+            spec["namespaceSelector"],
+            serde_json::json!({
+                "matchLabels": {"team": "shop"},
+                "matchExpressions": [{
+                    "key": "kubernetes.io/metadata.name",
+                    "operator": "NotIn",
+                    "values": ["kube-system", "gatekeeper-system"],
+                }],
+            })
+        );
+        assert_eq!(
+            spec["objectSelector"],
+            serde_json::json!({"matchExpressions": [{"key": "app", "operator": "Exists"}]})
+        );
+        assert_eq!(
+            conversion.unmapped,
+            vec!["spec.match.excludedNamespaces: kube-*", "spec.match.kinds"]
+        );
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                convert_yaml("{kind: K8sPSPFSGroup, metadata: {name: c}, spec: {}}").unwrap_err()
+            ),
+            "cannot convert K8sPSPFSGroup/c: spec.parameters.rule: is required"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn convert_security_context_constraints() -> Result<()> {
+        let conversions = convert_yaml(
+            r#"
+apiVersion: security.openshift.io/v1
+kind: SecurityContextConstraints
+metadata:
+  name: storage
+allowPrivilegedContainer: false
+allowedCapabilities: null
+fsGroup:
+  type: MustRunAs
+  ranges:
+  - min: 5000
+    max: 5999
+runAsUser:
+  type: MustRunAsRange
+users: []
+groups:
+- system:authenticated
+---
+apiVersion: security.openshift.io/v1
+kind: SecurityContextConstraints
+metadata:
+  name: anyuid
+fsGroup:
+  type: RunAsAny
+"#,
+        )?;
+
+        let storage = &conversions[0];
+        assert_eq!(storage.source, "SecurityContextConstraints/storage");
+        assert_eq!(
+            storage.settings["fsGroup"],
+            serde_json::json!({"rule": "MustRunAs", "ranges": [{"min": 5000, "max": 5999}]})
+        );
+        assert_eq!(storage.manifest["spec"]["mutating"], true);
+        assert_eq!(
+            storage.unmapped,
+            vec!["allowPrivilegedContainer", "runAsUser", "users", "groups"]
+        );
+
+        let anyuid = &conversions[1];
+        assert_eq!(
+            anyuid.settings["fsGroup"],
+            serde_json::json!({"rule": "RunAsAny"})
+        );
+        assert!(anyuid.unmapped.is_empty());
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                convert_yaml(
+                    "{kind: SecurityContextConstraints, metadata: {name: restricted-v2}, \
+                     fsGroup: {type: MustRunAs}}"
+                )
+                .unwrap_err()
+            ),
+            "cannot convert SecurityContextConstraints/restricted-v2: fsGroup.ranges: no \
+             equivalent for the ranges of the openshift.io/sa.scc.supplemental-groups namespace \
+             annotation, pass the ranges explicitly"
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                convert_yaml(
+                    "{kind: SecurityContextConstraints, metadata: {name: scc}, \
+                     fsGroup: {type: MustRunAsRange}}"
+                )
+                .unwrap_err()
+            ),
+            "cannot convert SecurityContextConstraints/scc: fsGroup.type: unknown strategy \
+             `MustRunAsRange`, expected `MustRunAs` or `RunAsAny`"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_pod_security_policies() {
+        let error = |yaml: &str| format!("{:#}", convert_yaml(yaml).unwrap_err());
+
+        assert_eq!(
+            error("{kind: PodSecurityPolicy, metadata: {name: psp}, spec: {privileged: true}}"),
+            "cannot convert PodSecurityPolicy/psp: spec.fsGroup: is required"
+        );
+        assert_eq!(
+            error(
+                "{kind: PodSecurityPolicy, metadata: {name: psp}, \
+                 spec: {fsGroup: {rule: MustRunAsNonRoot}}}"
+            ),
+            "cannot convert PodSecurityPolicy/psp: spec.fsGroup.rule: unknown variant \
+             `MustRunAsNonRoot`, expected one of `MustRunAs`, `MayRunAs`, `RunAsAny`"
+        );
+        // The extensions of the settings are not valid PodSecurityPolicies
+        assert_eq!(
+            error(
+                "{kind: PodSecurityPolicy, metadata: {name: psp}, \
+                 spec: {fsGroup: {rule: MustNotRunAs, ranges: [{min: 0, max: 0}]}}}"
+            ),
+            "cannot convert PodSecurityPolicy/psp: spec.fsGroup.rule: unknown variant \
+             `MustNotRunAs`, expected one of `MustRunAs`, `MayRunAs`, `RunAsAny`"
+        );
+        assert_eq!(
+            error(
+                "{kind: PodSecurityPolicy, metadata: {name: psp}, \
+                 spec: {fsGroup: {rule: MayRunAs, values: [1000]}}}"
+            ),
+            "cannot convert PodSecurityPolicy/psp: spec.fsGroup.values: unknown field `values`, \
+             expected `rule` or `ranges`"
+        );
+        assert_eq!(
+            error(
+                "{kind: PodSecurityPolicy, metadata: {name: psp}, \
+                 spec: {fsGroup: {rule: MayRunAs, ranges: [\"1000-2000\"]}}}"
+            ),
+            "cannot convert PodSecurityPolicy/psp: spec.fsGroup.ranges[0]: invalid type: \
+             string \"1000-2000\", expected a range with min and max"
+        );
+        assert_eq!(
+            error(
+                "{kind: K8sPSPFSGroup, metadata: {name: fsgroup}, \
+                 spec: {parameters: {rule: MayRunAs, ranges: [{min: 1000}]}}}"
+            ),
+            "cannot convert K8sPSPFSGroup/fsgroup: spec.parameters.ranges[0]: missing field `max`"
+        );
+        assert_eq!(
+            error("{kind: PodSecurityPolicy, metadata: {name: psp}, spec: {fsGroup: {rule: MustRunAs}}}"),
+            "cannot convert PodSecurityPolicy/psp: invalid settings: \
+             fsGroup.ranges: MustRunAs must contain at least one range"
+        );
+        assert_eq!(
+            error("{kind: Pod, metadata: {name: nginx}}"),
+            "cannot convert Pod/nginx: unsupported kind"
+        );
+    }
+}
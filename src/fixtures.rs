@@ -0,0 +1,119 @@
+//! Runs the cases of test_data/cases through `validate`, the same entry point
+//! the policy server calls. Each case is a YAML file with the settings, the
+//! AdmissionRequest, either inline or as the name of a file of test_data, and
+//! the expected response, where a mutation is described by the JSON patch
+//! applied to the object of the request.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fs, path::Path};
+
+const TEST_DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    settings: Value,
+    request: Request,
+    expected: Expected,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Request {
+    /// Name of a file of test_data
+    File(String),
+    Inline(Value),
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Expected {
+    accepted: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    warnings: Vec<String>,
+    #[serde(default)]
+    patch: Option<Value>,
+}
+
+fn run(case: &Case) -> Result<Expected> {
+    let request: Value = match &case.request {
+        Request::File(name) => {
+            let path = Path::new(TEST_DATA).join(name);
+            serde_json::from_slice(
+                &fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?,
+            )?
+        }
+        Request::Inline(request) => request.clone(),
+    };
+
+    let settings = serde_json::to_vec(&case.settings)?;
+    let response: Value = serde_json::from_slice(
+        &crate::validate_settings(&settings).map_err(|error| anyhow!("{error}"))?,
+    )?;
+    if response["valid"] != true {
+        return Err(anyhow!("invalid settings: {}", response["message"]));
+    }
+
+    let payload = serde_json::json!({"request": request, "settings": case.settings});
+    let response: Value = serde_json::from_slice(
+        &crate::validate(&serde_json::to_vec(&payload)?).map_err(|error| anyhow!("{error}"))?,
+    )?;
+    let mutated_object = &response["mutated_object"];
+    Ok(Expected {
+        accepted: response["accepted"] == true,
+        message: response["message"].as_str().map(str::to_string),
+        warnings: serde_json::from_value(response["warnings"].clone()).unwrap_or_default(),
+        patch: if mutated_object.is_null() {
+            None
+        } else {
+            Some(serde_json::to_value(json_patch::diff(
+                &request["object"],
+                mutated_object,
+            ))?)
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures() {
+        let mut paths: Vec<_> = fs::read_dir(Path::new(TEST_DATA).join("cases"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "yaml")
+            })
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no case found in test_data/cases");
+
+        let mut failures = Vec::new();
+        for path in &paths {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let case: Case = match serde_yaml::from_slice(&fs::read(path).unwrap()) {
+                Ok(case) => case,
+                Err(error) => {
+                    failures.push(format!("{name}: cannot parse the case: {error}"));
+                    continue;
+                }
+            };
+            match run(&case) {
+                Ok(actual) if actual == case.expected => {}
+                Ok(actual) => failures.push(format!(
+                    "{name}: expected {:?}, got {actual:?}",
+                    case.expected
+                )),
+                Err(error) => failures.push(format!("{name}: {error:#}")),
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
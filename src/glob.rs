@@ -0,0 +1,55 @@
+/// Matches `value` against a shell-like `pattern`, where `*` matches any
+/// sequence of characters, including the empty one, and `?` matches exactly
+/// one character
+pub(crate) fn matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    // Position of the last `*` in the pattern, and of the value character it
+    // was matched up to, used to backtrack when the rest of the pattern fails
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches() {
+        assert!(matches("backup/velero", "backup/velero"));
+        assert!(matches("backup/*", "backup/velero"));
+        assert!(matches("*/velero", "backup/velero"));
+        assert!(matches("*", ""));
+        assert!(matches("back?p/*-agent", "backup/restic-agent"));
+        assert!(matches("a*b*c", "axxbyyc"));
+
+        assert!(!matches("backup/velero", "backup/velero-2"));
+        assert!(!matches("backup/*", "default/velero"));
+        assert!(!matches("?", ""));
+        assert!(!matches("a*b*c", "axxbyy"));
+    }
+}
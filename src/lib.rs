@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use std::sync::LazyLock;
 
 use guest::prelude::*;
 use kubewarden_policy_sdk::wapc_guest as guest;
@@ -6,107 +7,493 @@ use kubewarden_policy_sdk::wapc_guest as guest;
 use k8s_openapi::api::core::v1 as apicore;
 
 extern crate kubewarden_policy_sdk as kubewarden;
-use kubewarden::{protocol_version_guest, request::ValidationRequest, validate_settings};
+use kubewarden::{
+    logging, protocol_version_guest,
+    request::{KubernetesAdmissionRequest, ValidationRequest},
+    response::ValidationResponse,
+    settings::{SettingsValidationResponse, Validatable},
+};
 
+use slog::{o, warn, Logger};
+
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "convert")]
+pub mod convert;
+#[cfg(test)]
+mod fixtures;
+mod glob;
+#[cfg(feature = "convert")]
+pub mod manifest;
+#[cfg(all(test, feature = "schema"))]
+mod questions;
+mod selector;
 mod settings;
-use settings::{Ranges, Rule, Settings};
+#[cfg(feature = "cli")]
+mod simulate;
+#[cfg(feature = "cli")]
+mod suggest;
+use settings::{AdvisoryCheck, MirrorPods, Ranges, Rule, Settings};
+
+/// Annotation set by the kubelet on the mirror pods of static pods
+const MIRROR_POD_ANNOTATION: &str = "kubernetes.io/config.mirror";
+
+/// Group of the kubelets, which create the mirror pods
+const NODES_GROUP: &str = "system:nodes";
+
+/// Prefix of the usernames of the kubelets
+const NODE_USERNAME_PREFIX: &str = "system:node:";
+
+/// Pod subresource used by `kubectl debug` to add ephemeral containers
+const EPHEMERAL_CONTAINERS_SUBRESOURCE: &str = "ephemeralcontainers";
+
+static LOG_DRAIN: LazyLock<Logger> = LazyLock::new(|| {
+    Logger::root(
+        logging::KubewardenDrain::new(),
+        o!("policy" => "psp-allowed-fsgroups"),
+    )
+});
 
 #[no_mangle]
 pub extern "C" fn wapc_init() {
     register_function("validate", validate);
-    register_function("validate_settings", validate_settings::<Settings>);
+    register_function("validate_settings", validate_settings);
     register_function("protocol_version", protocol_version_guest);
 }
 
+fn validate_settings(payload: &[u8]) -> CallResult {
+    let response = match Settings::from_slice(payload) {
+        Ok(settings) => {
+            // Redundant ranges are not an error, unless `strictRanges` is
+            // set, but they are worth reporting to the policy author
+            if !settings.strict_ranges {
+                for warning in settings.range_warnings() {
+                    warn!(LOG_DRAIN, "{}", warning);
+                }
+            }
+            match settings.validate() {
+                Ok(()) => SettingsValidationResponse {
+                    valid: true,
+                    message: None,
+                },
+                Err(message) => SettingsValidationResponse {
+                    valid: false,
+                    message: Some(message),
+                },
+            }
+        }
+        Err(message) => SettingsValidationResponse {
+            valid: false,
+            message: Some(message),
+        },
+    };
+    Ok(serde_json::to_vec(&response)?)
+}
+
 #[derive(Debug, PartialEq)]
 enum PolicyResponse {
     Accept,
+    AcceptWithWarnings(Vec<String>),
     Reject(String),
     Mutate(serde_json::Value),
+    MutateWithWarnings(serde_json::Value, Vec<String>),
 }
 
 fn validate(payload: &[u8]) -> CallResult {
     let validation_request: ValidationRequest<Settings> = ValidationRequest::new(payload)?;
 
-    let pod = match serde_json::from_value::<apicore::Pod>(validation_request.request.object) {
-        Ok(pod) => pod,
-        Err(_) => return kubewarden::accept_request(),
-    };
-
-    let settings = validation_request.settings;
-
-    match do_validate(pod, settings)? {
+    match evaluate(&validation_request.request, validation_request.settings)? {
         PolicyResponse::Accept => kubewarden::accept_request(),
+        PolicyResponse::AcceptWithWarnings(warnings) => accept_request_with_warnings(warnings),
         PolicyResponse::Reject(message) => {
             kubewarden::reject_request(Some(message), None, None, None)
         }
         PolicyResponse::Mutate(mutated_object) => kubewarden::mutate_request(mutated_object),
+        PolicyResponse::MutateWithWarnings(mutated_object, warnings) => {
+            mutate_request_with_warnings(mutated_object, warnings)
+        }
     }
 }
 
-fn do_validate(pod: apicore::Pod, settings: settings::Settings) -> Result<PolicyResponse> {
-    let pod_spec = pod.spec.ok_or_else(|| anyhow!("invalid pod spec"))?;
+/// Evaluates the admission request, accepting the objects that are not pods
+fn evaluate(request: &KubernetesAdmissionRequest, settings: Settings) -> Result<PolicyResponse> {
+    // Most objects deserialize into a Pod, as all of its fields are optional
+    if !request.kind.kind.is_empty() && request.kind.kind != "Pod" {
+        return Ok(PolicyResponse::Accept);
+    }
+    match serde_json::from_value::<apicore::Pod>(request.object.clone()) {
+        Ok(pod) => do_validate(pod, settings, request),
+        Err(_) => Ok(PolicyResponse::Accept),
+    }
+}
 
-    match settings.rule {
-        Rule::MustRunAs(ranges) => {
-            let pod_with_defaulted_fs_group = apicore::Pod {
-                spec: Some(apicore::PodSpec {
-                    security_context: Some(apicore::PodSecurityContext {
-                        fs_group: Some(
-                            ranges.ranges.first().unwrap().min, // It is safe to unwrap here because the settings
-                                                                // validation ensure that there is at least one range
-                                                                // in the list
-                        ),
-                        ..apicore::PodSecurityContext::default()
+fn accept_request_with_warnings(warnings: Vec<String>) -> CallResult {
+    Ok(serde_json::to_vec(&ValidationResponse {
+        accepted: true,
+        message: None,
+        code: None,
+        mutated_object: None,
+        audit_annotations: None,
+        warnings: Some(warnings),
+    })?)
+}
+
+fn mutate_request_with_warnings(
+    mutated_object: serde_json::Value,
+    warnings: Vec<String>,
+) -> CallResult {
+    Ok(serde_json::to_vec(&ValidationResponse {
+        accepted: true,
+        message: None,
+        code: None,
+        mutated_object: Some(mutated_object),
+        audit_annotations: None,
+        warnings: Some(warnings),
+    })?)
+}
+
+fn do_validate(
+    pod: apicore::Pod,
+    settings: settings::Settings,
+    request: &KubernetesAdmissionRequest,
+) -> Result<PolicyResponse> {
+    if settings.is_exempt(&pod) {
+        return Ok(PolicyResponse::Accept);
+    }
+
+    let mirror_pod = is_mirror_pod(&pod, request);
+    if mirror_pod {
+        match settings.mirror_pods {
+            MirrorPods::Accept => return Ok(PolicyResponse::Accept),
+            MirrorPods::Warn => {
+                return Ok(PolicyResponse::AcceptWithWarnings(vec![format!(
+                    "fsGroup of mirror pod {} is not validated",
+                    pod.metadata.name.as_deref().unwrap_or_default()
+                )]))
+            }
+            MirrorPods::Validate => {}
+        }
+    }
+
+    if request.sub_resource == EPHEMERAL_CONTAINERS_SUBRESOURCE {
+        // The pod level fsGroup cannot change through this subresource, only
+        // the ephemeral containers being added are validated
+        let old_pod =
+            serde_json::from_value::<apicore::Pod>(request.old_object.clone()).unwrap_or_default();
+        let ephemeral_containers = new_ephemeral_containers(&pod, &old_pod);
+        return validate_profiles(&pod, &settings, request, |rule| {
+            Ok(validate_ephemeral_containers(&ephemeral_containers, rule))
+        });
+    }
+
+    let warnings = |pod: &apicore::Pod| match &pod.spec {
+        Some(pod_spec) => advisory_warnings(pod_spec, &settings.advisory_checks),
+        None => Vec::new(),
+    };
+
+    match validate_profiles(&pod, &settings, request, |rule| validate_rule(&pod, rule))? {
+        // Mirror pods cannot be mutated, defaulting the fsGroup is skipped
+        PolicyResponse::Mutate(_) if mirror_pod => Ok(accept_with(warnings(&pod))),
+        PolicyResponse::Accept => Ok(accept_with(warnings(&pod))),
+        // The warnings are about the pod as it is admitted, with its
+        // defaulted fsGroup
+        PolicyResponse::Mutate(mutated_object) => {
+            let mutated_pod = serde_json::from_value::<apicore::Pod>(mutated_object.clone())?;
+            match warnings(&mutated_pod) {
+                warnings if warnings.is_empty() => Ok(PolicyResponse::Mutate(mutated_object)),
+                warnings => Ok(PolicyResponse::MutateWithWarnings(mutated_object, warnings)),
+            }
+        }
+        response => Ok(response),
+    }
+}
+
+fn accept_with(warnings: Vec<String>) -> PolicyResponse {
+    if warnings.is_empty() {
+        PolicyResponse::Accept
+    } else {
+        PolicyResponse::AcceptWithWarnings(warnings)
+    }
+}
+
+/// Anyone creating a pod can set the mirror pod annotation, or a Node owner
+/// reference, so the annotation only identifies a mirror pod when the pod
+/// comes from a kubelet, whose identity the NodeRestriction admission plugin
+/// enforces
+fn is_mirror_pod(pod: &apicore::Pod, request: &KubernetesAdmissionRequest) -> bool {
+    let annotated = pod
+        .metadata
+        .annotations
+        .as_ref()
+        .is_some_and(|annotations| annotations.contains_key(MIRROR_POD_ANNOTATION));
+    let from_node = request.user_info.groups.contains(NODES_GROUP)
+        || request.user_info.username.starts_with(NODE_USERNAME_PREFIX);
+    annotated && from_node
+}
+
+/// Evaluates the rules of the profiles whose subjects and selectors match
+/// the request, falling back to the top level rule when no profile matches.
+/// Following PodSecurityPolicy ordering, the first rule that accepts the pod
+/// as it is wins over the first rule that would mutate it.
+fn validate_profiles(
+    pod: &apicore::Pod,
+    settings: &Settings,
+    request: &KubernetesAdmissionRequest,
+    validate_rule: impl Fn(&Rule) -> Result<PolicyResponse>,
+) -> Result<PolicyResponse> {
+    let pod_service_account = pod_service_account(pod, request);
+    let mut rules = settings
+        .profiles
+        .iter()
+        .filter(|profile| profile.matches(&request.user_info, &pod_service_account, &pod.metadata))
+        .map(|profile| &profile.fs_group)
+        .peekable();
+    let rules: Vec<&Rule> = if rules.peek().is_some() {
+        rules.collect()
+    } else {
+        vec![&settings.fs_group]
+    };
+
+    let mut mutation = None;
+    let mut rejection = None;
+    for rule in rules {
+        match validate_rule(rule)? {
+            PolicyResponse::Accept => return Ok(PolicyResponse::Accept),
+            response @ PolicyResponse::Mutate(_) => {
+                mutation.get_or_insert(response);
+            }
+            response => {
+                rejection.get_or_insert(response);
+            }
+        }
+    }
+    mutation
+        .or(rejection)
+        .ok_or_else(|| anyhow!("no rule evaluated for the request"))
+}
+
+/// Returns the service account the pod runs as, in the `namespace/name` form
+fn pod_service_account(pod: &apicore::Pod, request: &KubernetesAdmissionRequest) -> String {
+    let namespace = if request.namespace.is_empty() {
+        pod.metadata.namespace.as_deref().unwrap_or("default")
+    } else {
+        request.namespace.as_str()
+    };
+    let name = pod
+        .spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.service_account_name.as_deref())
+        .unwrap_or("default");
+    format!("{namespace}/{name}")
+}
+
+fn validate_rule(pod: &apicore::Pod, rule: &Rule) -> Result<PolicyResponse> {
+    let pod_spec = pod
+        .spec
+        .as_ref()
+        .ok_or_else(|| anyhow!("invalid pod spec"))?;
+    let fs_group = pod_spec
+        .security_context
+        .as_ref()
+        .and_then(|security_context| security_context.fs_group);
+
+    match rule {
+        Rule::MustRunAs(ranges) => match fs_group {
+            Some(fs_group) => Ok(validate_fs_group(fs_group, ranges)),
+            None => {
+                // Like PodSecurityPolicies, the default is the `min` of the
+                // first range as written, not of the normalized ranges, so
+                // that it stays under the control of the settings author
+                let pod_with_defaulted_fs_group = apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(
+                                ranges.ranges.first().unwrap().min, // It is safe to unwrap here because the settings
+                                                                    // validation ensure that there is at least one range
+                                                                    // in the list
+                            ),
+                            ..pod_spec.security_context.clone().unwrap_or_default()
+                        }),
+                        ..pod_spec.clone()
                     }),
-                    ..pod_spec
-                }),
-                ..pod
-            };
-            if let Some(security_context) = pod_spec.security_context {
-                match security_context.fs_group {
-                    Some(fs_group) => Ok(validate_fs_group(fs_group, ranges)),
-                    None => Ok(PolicyResponse::Mutate(serde_json::to_value(
-                        pod_with_defaulted_fs_group,
-                    )?)),
-                }
-            } else {
+                    ..pod.clone()
+                };
                 Ok(PolicyResponse::Mutate(serde_json::to_value(
                     pod_with_defaulted_fs_group,
                 )?))
             }
-        }
-        Rule::MayRunAs(ranges) => {
-            if let Some(security_context) = pod_spec.security_context {
-                match security_context.fs_group {
-                    Some(fs_group) => Ok(validate_fs_group(fs_group, ranges)),
-                    None => Ok(PolicyResponse::Accept),
-                }
-            } else {
-                Ok(PolicyResponse::Accept)
-            }
-        }
+        },
+        Rule::MayRunAs(ranges) => match fs_group {
+            Some(fs_group) => Ok(validate_fs_group(fs_group, ranges)),
+            None => Ok(PolicyResponse::Accept),
+        },
+        Rule::MustNotRunAs(ranges) => match fs_group {
+            Some(fs_group) if ranges.contains(fs_group) => Ok(PolicyResponse::Reject(format!(
+                "fsGroup {fs_group} is included in a forbidden range"
+            ))),
+            _ => Ok(PolicyResponse::Accept),
+        },
         Rule::RunAsAny => Ok(PolicyResponse::Accept),
     }
 }
 
-fn validate_fs_group(fs_group: i64, ranges: Ranges) -> PolicyResponse {
-    if ranges
-        .ranges
-        .iter()
-        .any(|range| fs_group >= range.min && fs_group <= range.max)
-    {
+fn validate_fs_group(fs_group: i64, ranges: &Ranges) -> PolicyResponse {
+    if ranges.contains(fs_group) {
         PolicyResponse::Accept
     } else {
         PolicyResponse::Reject(format!("fsGroup {fs_group} is not included in any range"))
     }
 }
 
+/// Returns the ephemeral containers of `pod` that are not present in `old_pod`
+fn new_ephemeral_containers<'a>(
+    pod: &'a apicore::Pod,
+    old_pod: &apicore::Pod,
+) -> Vec<&'a apicore::EphemeralContainer> {
+    let old_names: Vec<&str> = old_pod
+        .spec
+        .iter()
+        .flat_map(|pod_spec| pod_spec.ephemeral_containers.iter().flatten())
+        .map(|container| container.name.as_str())
+        .collect();
+    pod.spec
+        .iter()
+        .flat_map(|pod_spec| pod_spec.ephemeral_containers.iter().flatten())
+        .filter(|container| !old_names.contains(&container.name.as_str()))
+        .collect()
+}
+
+fn validate_ephemeral_containers(
+    ephemeral_containers: &[&apicore::EphemeralContainer],
+    rule: &Rule,
+) -> PolicyResponse {
+    let (ranges, forbidden) = match rule {
+        Rule::MustRunAs(ranges) | Rule::MayRunAs(ranges) => (ranges, false),
+        Rule::MustNotRunAs(ranges) => (ranges, true),
+        Rule::RunAsAny => return PolicyResponse::Accept,
+    };
+    for container in ephemeral_containers {
+        let Some(run_as_group) = container
+            .security_context
+            .as_ref()
+            .and_then(|security_context| security_context.run_as_group)
+        else {
+            continue;
+        };
+        if forbidden && ranges.contains(run_as_group) {
+            return PolicyResponse::Reject(format!(
+                "runAsGroup {run_as_group} of ephemeral container {} is included in a forbidden range",
+                container.name
+            ));
+        }
+        if !forbidden && !ranges.contains(run_as_group) {
+            return PolicyResponse::Reject(format!(
+                "runAsGroup {run_as_group} of ephemeral container {} is not included in any range",
+                container.name
+            ));
+        }
+    }
+    PolicyResponse::Accept
+}
+
+fn advisory_warnings(pod_spec: &apicore::PodSpec, checks: &[AdvisoryCheck]) -> Vec<String> {
+    let fs_group = pod_spec
+        .security_context
+        .as_ref()
+        .and_then(|security_context| security_context.fs_group);
+
+    let mut warnings = Vec::new();
+    for check in checks {
+        match check {
+            AdvisoryCheck::RootFsGroup => {
+                if fs_group == Some(0) {
+                    warnings.push(
+                        "fsGroup 0 grants the root group access to the pod volumes".to_string(),
+                    );
+                }
+            }
+            AdvisoryCheck::MissingFsGroupWithPvc => {
+                let mounts_pvc = pod_spec
+                    .volumes
+                    .iter()
+                    .flatten()
+                    .any(|volume| volume.persistent_volume_claim.is_some());
+                if fs_group.is_none() && mounts_pvc {
+                    warnings.push(
+                        "pod mounts PersistentVolumeClaims without setting an fsGroup".to_string(),
+                    );
+                }
+            }
+            AdvisoryCheck::FsGroupRunAsGroupMismatch => {
+                let Some(fs_group) = fs_group else {
+                    continue;
+                };
+                if let Some(run_as_group) = pod_spec
+                    .security_context
+                    .as_ref()
+                    .and_then(|security_context| security_context.run_as_group)
+                    .filter(|run_as_group| *run_as_group != fs_group)
+                {
+                    warnings.push(format!(
+                        "fsGroup {fs_group} does not match the pod runAsGroup {run_as_group}"
+                    ));
+                }
+                for container in pod_spec
+                    .init_containers
+                    .iter()
+                    .flatten()
+                    .chain(pod_spec.containers.iter())
+                {
+                    if let Some(run_as_group) = container
+                        .security_context
+                        .as_ref()
+                        .and_then(|security_context| security_context.run_as_group)
+                        .filter(|run_as_group| *run_as_group != fs_group)
+                    {
+                        warnings.push(format!(
+                            "fsGroup {fs_group} does not match the runAsGroup {run_as_group} of container {}",
+                            container.name
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    warnings
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use settings::Range;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{
+        LabelSelector, ObjectMeta, OwnerReference,
+    };
+    use kubewarden::request::UserInfo;
+    use settings::{Exemptions, Profile, Range, Subjects};
+
+    fn request_from_group(group: &str) -> KubernetesAdmissionRequest {
+        KubernetesAdmissionRequest {
+            user_info: UserInfo {
+                username: "alice".to_string(),
+                groups: vec![group.to_string()].into_iter().collect(),
+                ..UserInfo::default()
+            },
+            ..KubernetesAdmissionRequest::default()
+        }
+    }
+
+    fn storage_admins_profile(rule: Rule) -> Profile {
+        Profile {
+            subjects: Subjects {
+                groups: vec!["storage-admins".to_string()],
+                ..Subjects::default()
+            },
+            pod_selector: None,
+            pod_annotation_selector: None,
+            fs_group: rule,
+        }
+    }
 
     #[test]
     fn run_as_any_always_accepts() -> Result<()> {
@@ -117,8 +504,10 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::RunAsAny
-                }
+                    fs_group: Rule::RunAsAny,
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -135,13 +524,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MayRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -161,13 +550,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MayRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -190,13 +579,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MayRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -219,16 +608,16 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MayRunAs(Ranges {
-                        ranges: vec![
-                            Range { min: 100, max: 200 },
-                            Range {
-                                min: 1000,
-                                max: 2000,
-                            }
-                        ]
-                    })
-                }
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![
+                        Range { min: 100, max: 200 },
+                        Range {
+                            min: 1000,
+                            max: 2000,
+                        }
+                    ])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -251,13 +640,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MayRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
         );
@@ -274,13 +663,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
                 spec: Some(apicore::PodSpec {
@@ -306,19 +695,19 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![
-                            Range {
-                                min: 3000,
-                                max: 4000,
-                            },
-                            Range {
-                                min: 1000,
-                                max: 2000,
-                            }
-                        ]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![
+                        Range {
+                            min: 3000,
+                            max: 4000,
+                        },
+                        Range {
+                            min: 1000,
+                            max: 2000,
+                        }
+                    ])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
                 spec: Some(apicore::PodSpec {
@@ -347,13 +736,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
                 spec: Some(apicore::PodSpec {
@@ -382,19 +771,19 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![
-                            Range {
-                                min: 3000,
-                                max: 4000,
-                            },
-                            Range {
-                                min: 1000,
-                                max: 2000,
-                            }
-                        ]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![
+                        Range {
+                            min: 3000,
+                            max: 4000,
+                        },
+                        Range {
+                            min: 1000,
+                            max: 2000,
+                        }
+                    ])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
                 spec: Some(apicore::PodSpec {
@@ -411,6 +800,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn must_run_as_mutates_keeping_security_context() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            run_as_user: Some(1000),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
+                spec: Some(apicore::PodSpec {
+                    security_context: Some(apicore::PodSecurityContext {
+                        fs_group: Some(1000),
+                        run_as_user: Some(1000),
+                        ..apicore::PodSecurityContext::default()
+                    }),
+                    ..apicore::PodSpec::default()
+                }),
+                ..apicore::Pod::default()
+            })?)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn must_run_as_accepts_with_fsgroup_in_range() -> Result<()> {
         assert_eq!(
@@ -426,13 +854,13 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -455,16 +883,16 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![
-                            Range { min: 100, max: 200 },
-                            Range {
-                                min: 1000,
-                                max: 2000,
-                            }
-                        ]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![
+                        Range { min: 100, max: 200 },
+                        Range {
+                            min: 1000,
+                            max: 2000,
+                        }
+                    ])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Accept
         );
@@ -487,17 +915,857 @@ mod tests {
                     ..apicore::Pod::default()
                 },
                 Settings {
-                    rule: Rule::MustRunAs(Ranges {
-                        ranges: vec![Range {
-                            min: 1000,
-                            max: 2000,
-                        }]
-                    })
-                }
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
             )?,
             PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
         );
 
         Ok(())
     }
+
+    #[test]
+    fn may_run_as_warns_with_root_fsgroup() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(0),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range { min: 0, max: 2000 }])),
+                    advisory_checks: vec![AdvisoryCheck::RootFsGroup],
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::AcceptWithWarnings(vec![
+                "fsGroup 0 grants the root group access to the pod volumes".to_string()
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_as_any_warns_with_pvc_and_empty_fsgroup() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        volumes: Some(vec![apicore::Volume {
+                            name: "data".to_string(),
+                            persistent_volume_claim: Some(
+                                apicore::PersistentVolumeClaimVolumeSource {
+                                    claim_name: "data".to_string(),
+                                    ..apicore::PersistentVolumeClaimVolumeSource::default()
+                                }
+                            ),
+                            ..apicore::Volume::default()
+                        }]),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::RunAsAny,
+                    advisory_checks: vec![
+                        AdvisoryCheck::RootFsGroup,
+                        AdvisoryCheck::MissingFsGroupWithPvc
+                    ],
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::AcceptWithWarnings(vec![
+                "pod mounts PersistentVolumeClaims without setting an fsGroup".to_string()
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn may_run_as_warns_with_fsgroup_not_matching_run_as_group() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(1000),
+                            run_as_group: Some(1000),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        containers: vec![apicore::Container {
+                            name: "nginx".to_string(),
+                            security_context: Some(apicore::SecurityContext {
+                                run_as_group: Some(2000),
+                                ..apicore::SecurityContext::default()
+                            }),
+                            ..apicore::Container::default()
+                        }],
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    advisory_checks: vec![AdvisoryCheck::FsGroupRunAsGroupMismatch],
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::AcceptWithWarnings(vec![
+                "fsGroup 1000 does not match the runAsGroup 2000 of container nginx".to_string()
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn must_run_as_warns_about_the_defaulted_fsgroup() -> Result<()> {
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                security_context: Some(apicore::PodSecurityContext {
+                    run_as_group: Some(2000),
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                pod,
+                Settings {
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    advisory_checks: vec![AdvisoryCheck::FsGroupRunAsGroupMismatch],
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::MutateWithWarnings(
+                serde_json::to_value(apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(1000),
+                            run_as_group: Some(2000),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                })?,
+                vec!["fsGroup 1000 does not match the pod runAsGroup 2000".to_string()]
+            )
+        );
+
+        // Mirror pods are not mutated, but they are still warned about
+        let mirror_pod = apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                volumes: Some(vec![apicore::Volume {
+                    name: "data".to_string(),
+                    persistent_volume_claim: Some(apicore::PersistentVolumeClaimVolumeSource {
+                        claim_name: "data".to_string(),
+                        ..apicore::PersistentVolumeClaimVolumeSource::default()
+                    }),
+                    ..apicore::Volume::default()
+                }]),
+                ..apicore::PodSpec::default()
+            }),
+            ..mirror_pod(None)
+        };
+        assert_eq!(
+            do_validate(
+                mirror_pod,
+                Settings {
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    advisory_checks: vec![AdvisoryCheck::MissingFsGroupWithPvc],
+                    ..Settings::default()
+                },
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::AcceptWithWarnings(vec![
+                "pod mounts PersistentVolumeClaims without setting an fsGroup".to_string()
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn may_run_as_rejects_with_fsgroup_in_no_range_without_warnings() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(0),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    advisory_checks: vec![AdvisoryCheck::RootFsGroup],
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Reject("fsGroup 0 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_apply_to_matching_subjects_only() -> Result<()> {
+        let settings = || Settings {
+            fs_group: Rule::RunAsAny,
+            profiles: vec![storage_admins_profile(Rule::MustRunAs(Ranges::new(vec![
+                Range {
+                    min: 1000,
+                    max: 2000,
+                },
+            ])))],
+            ..Settings::default()
+        };
+        let pod = apicore::Pod {
+            spec: Some(apicore::PodSpec::default()),
+            ..apicore::Pod::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                pod.clone(),
+                settings(),
+                &request_from_group("storage-admins")
+            )?,
+            PolicyResponse::Mutate(serde_json::to_value(apicore::Pod {
+                spec: Some(apicore::PodSpec {
+                    security_context: Some(apicore::PodSecurityContext {
+                        fs_group: Some(1000),
+                        ..apicore::PodSecurityContext::default()
+                    }),
+                    ..apicore::PodSpec::default()
+                }),
+                ..apicore::Pod::default()
+            })?)
+        );
+        assert_eq!(
+            do_validate(pod, settings(), &request_from_group("developers"))?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_prefer_non_mutating_rules() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec::default()),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    profiles: vec![
+                        storage_admins_profile(Rule::MustRunAs(Ranges::new(vec![Range {
+                            min: 1000,
+                            max: 2000,
+                        }]))),
+                        storage_admins_profile(Rule::MayRunAs(Ranges::new(vec![Range {
+                            min: 1000,
+                            max: 2000,
+                        }]))),
+                    ],
+                    ..Settings::default()
+                },
+                &request_from_group("storage-admins")
+            )?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_reject_when_no_rule_admits_the_pod() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(100),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    profiles: vec![
+                        storage_admins_profile(Rule::MustRunAs(Ranges::new(vec![Range {
+                            min: 1000,
+                            max: 2000,
+                        }]))),
+                        storage_admins_profile(Rule::MayRunAs(Ranges::new(vec![Range {
+                            min: 3000,
+                            max: 4000,
+                        }]))),
+                    ],
+                    ..Settings::default()
+                },
+                &request_from_group("storage-admins")
+            )?,
+            PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_apply_to_matching_pod_service_accounts() -> Result<()> {
+        let pod = |service_account_name: &str| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                service_account_name: Some(service_account_name.to_string()),
+                security_context: Some(apicore::PodSecurityContext {
+                    fs_group: Some(3000),
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        };
+        let settings = || Settings {
+            fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            profiles: vec![Profile {
+                subjects: Subjects {
+                    pod_service_accounts: vec!["storage/*-agent".to_string()],
+                    ..Subjects::default()
+                },
+                pod_selector: None,
+                pod_annotation_selector: None,
+                fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                    min: 3000,
+                    max: 4000,
+                }])),
+            }],
+            ..Settings::default()
+        };
+        let request = KubernetesAdmissionRequest {
+            namespace: "storage".to_string(),
+            user_info: UserInfo {
+                username: "system:serviceaccount:kube-system:replicaset-controller".to_string(),
+                ..UserInfo::default()
+            },
+            ..KubernetesAdmissionRequest::default()
+        };
+
+        assert_eq!(
+            do_validate(pod("backup-agent"), settings(), &request)?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(pod("frontend"), settings(), &request)?,
+            PolicyResponse::Reject("fsGroup 3000 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    fn storage_pod(fs_group: i64) -> apicore::Pod {
+        apicore::Pod {
+            metadata: ObjectMeta {
+                labels: Some(
+                    vec![(
+                        "app.kubernetes.io/part-of".to_string(),
+                        "storage".to_string(),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
+                ..ObjectMeta::default()
+            },
+            spec: Some(apicore::PodSpec {
+                security_context: Some(apicore::PodSecurityContext {
+                    fs_group: Some(fs_group),
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        }
+    }
+
+    fn part_of_storage_selector() -> LabelSelector {
+        LabelSelector {
+            match_labels: Some(
+                vec![(
+                    "app.kubernetes.io/part-of".to_string(),
+                    "storage".to_string(),
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            ..LabelSelector::default()
+        }
+    }
+
+    #[test]
+    fn profiles_apply_to_pods_matching_selectors() -> Result<()> {
+        let settings = || Settings {
+            fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            profiles: vec![Profile {
+                subjects: Subjects::default(),
+                pod_selector: Some(part_of_storage_selector()),
+                pod_annotation_selector: None,
+                fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                    min: 3000,
+                    max: 4000,
+                }])),
+            }],
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                storage_pod(3000),
+                settings(),
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    metadata: ObjectMeta::default(),
+                    ..storage_pod(3000)
+                },
+                settings(),
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Reject("fsGroup 3000 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn exempt_pods_are_accepted() -> Result<()> {
+        let settings = || Settings {
+            fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            exemptions: Exemptions {
+                pod_selector: Some(part_of_storage_selector()),
+                ..Exemptions::default()
+            },
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                storage_pod(100),
+                settings(),
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    metadata: ObjectMeta::default(),
+                    ..storage_pod(100)
+                },
+                settings(),
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    fn mirror_pod(fs_group: Option<i64>) -> apicore::Pod {
+        apicore::Pod {
+            metadata: ObjectMeta {
+                name: Some("etcd-control-plane".to_string()),
+                annotations: Some(
+                    vec![(MIRROR_POD_ANNOTATION.to_string(), "c0ffee".to_string())]
+                        .into_iter()
+                        .collect(),
+                ),
+                owner_references: Some(vec![OwnerReference {
+                    api_version: "v1".to_string(),
+                    kind: "Node".to_string(),
+                    name: "control-plane".to_string(),
+                    uid: "c0ffee".to_string(),
+                    controller: Some(true),
+                    ..OwnerReference::default()
+                }]),
+                ..ObjectMeta::default()
+            },
+            spec: Some(apicore::PodSpec {
+                security_context: Some(apicore::PodSecurityContext {
+                    fs_group,
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        }
+    }
+
+    #[test]
+    fn mirror_pods_are_handled_explicitly() -> Result<()> {
+        let settings = |mirror_pods| Settings {
+            fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            mirror_pods,
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(100)),
+                settings(MirrorPods::Accept),
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(100)),
+                settings(MirrorPods::Warn),
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::AcceptWithWarnings(vec![
+                "fsGroup of mirror pod etcd-control-plane is not validated".to_string()
+            ])
+        );
+        assert_eq!(
+            do_validate(
+                mirror_pod(None),
+                settings(MirrorPods::Validate),
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(100)),
+                settings(MirrorPods::Validate),
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
+        );
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(100)),
+                settings(MirrorPods::default()),
+                &request_from_group(NODES_GROUP)
+            )?,
+            PolicyResponse::Reject("fsGroup 100 is not included in any range".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mirror_pod_annotation_alone_is_not_trusted() -> Result<()> {
+        let settings = |mirror_pods| Settings {
+            fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            mirror_pods,
+            ..Settings::default()
+        };
+        let request = |username: &str, groups: &[&str]| KubernetesAdmissionRequest {
+            user_info: UserInfo {
+                username: username.to_string(),
+                groups: groups.iter().map(|group| group.to_string()).collect(),
+                ..UserInfo::default()
+            },
+            ..KubernetesAdmissionRequest::default()
+        };
+        let alice = request("alice", &["system:authenticated"]);
+
+        // The annotation and the Node owner reference can be set by anyone
+        assert_eq!(
+            do_validate(mirror_pod(Some(0)), settings(MirrorPods::Accept), &alice)?,
+            PolicyResponse::Reject("fsGroup 0 is not included in any range".to_string())
+        );
+        assert!(matches!(
+            do_validate(mirror_pod(None), settings(MirrorPods::Validate), &alice)?,
+            PolicyResponse::Mutate(_)
+        ));
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(0)),
+                settings(MirrorPods::Accept),
+                &request("system:node:control-plane", &[])
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                mirror_pod(Some(0)),
+                settings(MirrorPods::Accept),
+                &request("kubelet", &["system:nodes", "system:authenticated"])
+            )?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ephemeral_containers_are_validated_on_their_subresource() -> Result<()> {
+        let ephemeral_container = |name: &str, run_as_group| apicore::EphemeralContainer {
+            name: name.to_string(),
+            security_context: Some(apicore::SecurityContext {
+                run_as_group,
+                ..apicore::SecurityContext::default()
+            }),
+            ..apicore::EphemeralContainer::default()
+        };
+        let pod = |ephemeral_containers| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                // Out of range, but immutable through the subresource
+                security_context: Some(apicore::PodSecurityContext {
+                    fs_group: Some(100),
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ephemeral_containers: Some(ephemeral_containers),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        };
+        let settings = || Settings {
+            fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                min: 1000,
+                max: 2000,
+            }])),
+            ..Settings::default()
+        };
+        let request = KubernetesAdmissionRequest {
+            sub_resource: "ephemeralcontainers".to_string(),
+            old_object: serde_json::to_value(pod(vec![ephemeral_container(
+                "debugger",
+                Some(100),
+            )]))?,
+            ..KubernetesAdmissionRequest::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                pod(vec![
+                    ephemeral_container("debugger", Some(100)),
+                    ephemeral_container("debugger-2", Some(1500)),
+                    ephemeral_container("debugger-3", None),
+                ]),
+                settings(),
+                &request
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                pod(vec![
+                    ephemeral_container("debugger", Some(100)),
+                    ephemeral_container("debugger-2", Some(0)),
+                ]),
+                settings(),
+                &request
+            )?,
+            PolicyResponse::Reject(
+                "runAsGroup 0 of ephemeral container debugger-2 is not included in any range"
+                    .to_string()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn must_run_as_with_open_ended_range() -> Result<()> {
+        let settings = || {
+            Settings::from_slice(br#"{"rule": "MustRunAs", "ranges": [{"min": 10000}]}"#)
+                .map_err(|error| anyhow!(error))
+        };
+        let pod = |fs_group| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                security_context: Some(apicore::PodSecurityContext {
+                    fs_group,
+                    ..apicore::PodSecurityContext::default()
+                }),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        };
+
+        assert_eq!(
+            do_validate(
+                pod(Some(settings::MAX_GID)),
+                settings()?,
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Accept
+        );
+        assert_eq!(
+            do_validate(
+                pod(Some(9999)),
+                settings()?,
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Reject("fsGroup 9999 is not included in any range".to_string())
+        );
+        assert_eq!(
+            do_validate(
+                pod(None),
+                settings()?,
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Mutate(serde_json::to_value(pod(Some(10000)))?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn must_not_run_as_accepts_with_empty_fsgroup() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec::default()),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MustNotRunAs(Ranges::new(vec![Range { min: 0, max: 99 }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn must_not_run_as_accepts_with_fsgroup_in_no_range() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(1000),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MustNotRunAs(Ranges::new(vec![Range { min: 0, max: 99 }])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn must_not_run_as_rejects_with_fsgroup_in_some_range() -> Result<()> {
+        assert_eq!(
+            do_validate(
+                apicore::Pod {
+                    spec: Some(apicore::PodSpec {
+                        security_context: Some(apicore::PodSecurityContext {
+                            fs_group: Some(0),
+                            ..apicore::PodSecurityContext::default()
+                        }),
+                        ..apicore::PodSpec::default()
+                    }),
+                    ..apicore::Pod::default()
+                },
+                Settings {
+                    fs_group: Rule::MustNotRunAs(Ranges::new(vec![
+                        Range {
+                            min: 65534,
+                            max: 65534,
+                        },
+                        Range { min: 0, max: 99 }
+                    ])),
+                    ..Settings::default()
+                },
+                &KubernetesAdmissionRequest::default()
+            )?,
+            PolicyResponse::Reject("fsGroup 0 is included in a forbidden range".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn other_kinds_are_accepted() -> Result<()> {
+        let request: KubernetesAdmissionRequest =
+            serde_json::from_str(include_str!("../test_data/ingress_creation.json"))?;
+        assert_eq!(
+            evaluate(
+                &request,
+                Settings {
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                        min: 1000,
+                        max: 2000,
+                    }])),
+                    ..Settings::default()
+                }
+            )?,
+            PolicyResponse::Accept
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    psp_allowed_fsgroups::cli::main()
+}
@@ -0,0 +1,249 @@
+//! Generates the Kubewarden policy manifests running the policy with given
+//! settings, so that `rules` and `mutating` do not have to be copied from
+//! metadata.yml by hand.
+
+use crate::settings::{Rule, Settings};
+use anyhow::{anyhow, Result};
+use kubewarden::settings::Validatable;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Module of the policy, at the version being built
+const MODULE: &str = concat!(
+    "registry://ghcr.io/kubewarden/policies/allowed-fsgroups-psp:v",
+    env!("CARGO_PKG_VERSION")
+);
+
+/// How the policy server handles the requests rejected by the policy
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Rejected requests are denied
+    Protect,
+    /// Rejected requests are only logged
+    Monitor,
+}
+
+/// Options of the generated manifest, other than the settings
+#[derive(Clone, Debug, Default)]
+pub struct ManifestOptions {
+    pub name: String,
+    /// Namespace of an AdmissionPolicy. A ClusterAdmissionPolicy is
+    /// generated when there is none.
+    pub namespace: Option<String>,
+    /// Label selector of the namespaces the ClusterAdmissionPolicy applies to
+    pub namespace_selector: Option<Value>,
+    /// Label selector of the pods the policy applies to
+    pub object_selector: Option<Value>,
+    /// Defaults to the mode of the policy server, `protect`
+    pub mode: Option<Mode>,
+    /// Defaults to the `default` policy server
+    pub policy_server: Option<String>,
+    /// URL of the policy module, defaults to the release of this version
+    pub module: Option<String>,
+}
+
+/// Generates the manifest of the policy running with the settings, in any
+/// version, which must be valid
+pub fn generate(settings: &Value, options: &ManifestOptions) -> Result<Value> {
+    let settings = Settings::from_slice(&serde_json::to_vec(settings)?)
+        .map_err(|error| anyhow!("invalid settings: {error}"))?;
+    settings
+        .validate()
+        .map_err(|error| anyhow!("invalid settings: {error}"))?;
+    manifest(&settings, options)
+}
+
+pub(crate) fn manifest(settings: &Settings, options: &ManifestOptions) -> Result<Value> {
+    let mut manifest = serde_json::json!({
+        "apiVersion": "policies.kubewarden.io/v1",
+        "kind": "ClusterAdmissionPolicy",
+        "metadata": {"name": options.name},
+        "spec": {
+            "module": options.module.as_deref().unwrap_or(MODULE),
+        },
+    });
+    if let Some(namespace) = &options.namespace {
+        if options.namespace_selector.is_some() {
+            return Err(anyhow!(
+                "namespaceSelector is only available for ClusterAdmissionPolicies"
+            ));
+        }
+        manifest["kind"] = "AdmissionPolicy".into();
+        manifest["metadata"]["namespace"] = namespace.as_str().into();
+    }
+
+    let spec = &mut manifest["spec"];
+    if let Some(mode) = options.mode {
+        spec["mode"] = serde_json::to_value(mode)?;
+    }
+    if let Some(policy_server) = &options.policy_server {
+        spec["policyServer"] = policy_server.as_str().into();
+    }
+    if let Some(namespace_selector) = &options.namespace_selector {
+        spec["namespaceSelector"] = namespace_selector.clone();
+    }
+    if let Some(object_selector) = &options.object_selector {
+        spec["objectSelector"] = object_selector.clone();
+    }
+    spec["rules"] = rules(settings);
+    spec["mutating"] = mutating(settings).into();
+    spec["settings"] = serde_json::to_value(settings)?;
+    Ok(manifest)
+}
+
+fn fs_group_rules(settings: &Settings) -> impl Iterator<Item = &Rule> {
+    std::iter::once(&settings.fs_group)
+        .chain(settings.profiles.iter().map(|profile| &profile.fs_group))
+}
+
+/// Same rules as metadata.yml: pods, and the ephemeral containers added to
+/// them, unless no rule constrains their `runAsGroup`
+fn rules(settings: &Settings) -> Value {
+    let mut rules = vec![serde_json::json!({
+        "apiGroups": [""],
+        "apiVersions": ["v1"],
+        "resources": ["pods"],
+        "operations": ["CREATE", "UPDATE"],
+    })];
+    if fs_group_rules(settings).any(|rule| !matches!(rule, Rule::RunAsAny)) {
+        rules.push(serde_json::json!({
+            "apiGroups": [""],
+            "apiVersions": ["v1"],
+            "resources": ["pods/ephemeralcontainers"],
+            "operations": ["UPDATE"],
+        }));
+    }
+    Value::Array(rules)
+}
+
+/// Only `MustRunAs` defaults the fsGroup of the pods, the other rules just
+/// accept or reject them
+fn mutating(settings: &Settings) -> bool {
+    fs_group_rules(settings).any(|rule| matches!(rule, Rule::MustRunAs(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(settings: &str) -> Settings {
+        Settings::from_slice(settings.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn mutating_follows_the_rules() {
+        let mutating = |settings_json: &str| mutating(&settings(settings_json));
+
+        assert!(mutating(r#"{"rule": "MustRunAs", "ranges": ["1000"]}"#));
+        assert!(!mutating(r#"{"rule": "MayRunAs", "ranges": ["1000"]}"#));
+        assert!(!mutating(r#"{"rule": "RunAsAny"}"#));
+        assert!(mutating(
+            r#"{
+                "rule": "RunAsAny",
+                "profiles": [{
+                    "subjects": {"podServiceAccounts": ["shop/*"]},
+                    "rule": "MustRunAs",
+                    "ranges": ["1000"]
+                }]
+            }"#
+        ));
+    }
+
+    #[test]
+    fn generate_manifests() -> Result<()> {
+        let options = ManifestOptions {
+            name: "fsgroups".to_string(),
+            namespace_selector: Some(serde_json::json!({"matchLabels": {"team": "shop"}})),
+            mode: Some(Mode::Monitor),
+            policy_server: Some("reserved".to_string()),
+            module: Some("file:///policy.wasm".to_string()),
+            ..ManifestOptions::default()
+        };
+        assert_eq!(
+            generate(
+                &serde_json::json!({"rule": "MayRunAs", "ranges": ["1000-2000"]}),
+                &options
+            )?,
+            serde_json::json!({
+                "apiVersion": "policies.kubewarden.io/v1",
+                "kind": "ClusterAdmissionPolicy",
+                "metadata": {"name": "fsgroups"},
+                "spec": {
+                    "module": "file:///policy.wasm",
+                    "mode": "monitor",
+                    "policyServer": "reserved",
+                    "namespaceSelector": {"matchLabels": {"team": "shop"}},
+                    "rules": [
+                        {
+                            "apiGroups": [""],
+                            "apiVersions": ["v1"],
+                            "resources": ["pods"],
+                            "operations": ["CREATE", "UPDATE"],
+                        },
+                        {
+                            "apiGroups": [""],
+                            "apiVersions": ["v1"],
+                            "resources": ["pods/ephemeralcontainers"],
+                            "operations": ["UPDATE"],
+                        },
+                    ],
+                    "mutating": false,
+                    "settings": {
+                        "apiVersion": "v2",
+                        "fsGroup": {"rule": "MayRunAs", "ranges": [{"min": 1000, "max": 2000}]},
+                        "mirrorPods": "Validate",
+                    },
+                },
+            })
+        );
+
+        let options = ManifestOptions {
+            name: "fsgroups".to_string(),
+            namespace: Some("shop".to_string()),
+            ..ManifestOptions::default()
+        };
+        let manifest = generate(&serde_json::json!({"rule": "RunAsAny"}), &options)?;
+        assert_eq!(manifest["kind"], "AdmissionPolicy");
+        assert_eq!(manifest["metadata"]["namespace"], "shop");
+        assert_eq!(manifest["spec"]["module"], MODULE);
+        assert_eq!(manifest["spec"]["mutating"], false);
+        assert_eq!(
+            manifest["spec"]["rules"],
+            serde_json::json!([{
+                "apiGroups": [""],
+                "apiVersions": ["v1"],
+                "resources": ["pods"],
+                "operations": ["CREATE", "UPDATE"],
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_manifest_options() {
+        let error = |settings: Value, options: ManifestOptions| {
+            generate(&settings, &options).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error(
+                serde_json::json!({"rule": "MustRunAs"}),
+                ManifestOptions::default()
+            ),
+            "invalid settings: ranges: MustRunAs must contain at least one range"
+        );
+        assert_eq!(
+            error(
+                serde_json::json!({"rule": "RunAsAny"}),
+                ManifestOptions {
+                    namespace: Some("shop".to_string()),
+                    namespace_selector: Some(serde_json::json!({})),
+                    ..ManifestOptions::default()
+                }
+            ),
+            "namespaceSelector is only available for ClusterAdmissionPolicies"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Generates questions-ui.yml, the form of the policy settings in the Rancher
+//! UI, out of the JSON Schema of the settings. Every setting is shown, in the
+//! order it is declared, and can be tuned with the `x-ui` schema extension:
+//! `label`, `default`, `show_if`, or `hidden` for the settings that the form
+//! cannot represent.
+
+use crate::settings::Settings;
+use serde::Serialize;
+use serde_json::Value;
+
+const HEADER: &str = "# Generated from the settings types by `make questions-ui`, do not edit\n";
+
+#[derive(Serialize)]
+struct Questions {
+    questions: Vec<Question>,
+}
+
+#[derive(Serialize)]
+struct Question {
+    variable: String,
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tooltip: Option<String>,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    options: Vec<String>,
+    required: bool,
+    group: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    show_if: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    hide_input: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sequence_questions: Vec<Question>,
+}
+
+pub(crate) fn generate() -> String {
+    let schema = serde_json::to_value(schemars::schema_for!(Settings)).unwrap();
+    let questions = schema["properties"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(_, property)| !property["x-ui"]["hidden"].as_bool().unwrap_or_default())
+        .map(|(variable, property)| question(variable, property, &schema))
+        .collect();
+    HEADER.to_string() + &serde_yaml::to_string(&Questions { questions }).unwrap()
+}
+
+fn question(variable: &str, property: &Value, root: &Value) -> Question {
+    let ui = &property["x-ui"];
+    let schema = resolve(property, root);
+    let mut question = Question {
+        variable: variable.to_string(),
+        label: ui["label"]
+            .as_str()
+            .map_or_else(|| label(variable), str::to_string),
+        description: None,
+        tooltip: property["description"]
+            .as_str()
+            .or_else(|| schema["description"].as_str())
+            .map(|description| description.replace('\n', " ")),
+        kind: "string",
+        default: Some(&ui["default"])
+            .filter(|default| !default.is_null())
+            .or_else(|| Some(&property["default"]).filter(|default| !default.is_null()))
+            .cloned(),
+        options: Vec::new(),
+        required: false,
+        group: "Settings",
+        show_if: ui["show_if"].as_str().map(str::to_string),
+        hide_input: false,
+        sequence_questions: Vec::new(),
+    };
+
+    if let Some(variants) = variants(schema) {
+        question.kind = "enum";
+        question.description = describe(&variants);
+        question.options = variants.into_iter().map(|(name, _)| name).collect();
+        return question;
+    }
+    match types(schema).as_slice() {
+        ["boolean"] => question.kind = "boolean",
+        ["integer"] => question.kind = "int",
+        ["string"] => question.kind = "string",
+        ["array"] => {
+            let items = resolve(&schema["items"], root);
+            question.default.get_or_insert(Value::Array(Vec::new()));
+            if let Some(object) = object_variant(items) {
+                question.kind = "sequence[";
+                question.hide_input = true;
+                question.sequence_questions = object["properties"]
+                    .as_object()
+                    .unwrap()
+                    .iter()
+                    .map(|(variable, property)| sequence_question(variable, property, root))
+                    .collect();
+            } else if let Some(variants) = variants(items) {
+                question.kind = "array[";
+                question.description = describe(&variants);
+            } else if matches!(types(items).as_slice(), ["string"] | ["integer"]) {
+                question.kind = "array[";
+            } else {
+                unsupported(variable)
+            }
+        }
+        _ => unsupported(variable),
+    }
+    question
+}
+
+/// Same as `question`, for a field of the items of a sequence, which can only
+/// be a scalar
+fn sequence_question(variable: &str, property: &Value, root: &Value) -> Question {
+    let question = question(variable, property, root);
+    if question.kind.ends_with('[') {
+        unsupported(variable)
+    }
+    question
+}
+
+fn unsupported(variable: &str) -> ! {
+    panic!(
+        "setting {} cannot be shown in questions-ui.yml, \
+         hide it with #[schemars(extend(\"x-ui\" = {{\"hidden\": true}}))]",
+        variable
+    )
+}
+
+/// Follows `$ref`s, and unwraps the schemas of optional settings
+fn resolve<'a>(schema: &'a Value, root: &'a Value) -> &'a Value {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/$defs/");
+        return resolve(&root["$defs"][name], root);
+    }
+    if let Some([schema, null]) = schema["anyOf"].as_array().map(Vec::as_slice) {
+        if null["type"] == "null" {
+            return resolve(schema, root);
+        }
+    }
+    schema
+}
+
+/// Types of the schema, other than `null`
+fn types(schema: &Value) -> Vec<&str> {
+    match &schema["type"] {
+        Value::String(kind) => vec![kind.as_str()],
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|kind| *kind != "null")
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Values, and their descriptions, of an enum
+fn variants(schema: &Value) -> Option<Vec<(String, Option<String>)>> {
+    if let Some(values) = schema["enum"].as_array() {
+        return Some(
+            values
+                .iter()
+                .map(|value| (value.as_str().unwrap().to_string(), None))
+                .collect(),
+        );
+    }
+    let variants = schema["oneOf"].as_array()?;
+    variants
+        .iter()
+        .map(|variant| {
+            let value = variant["const"].as_str()?;
+            let description = variant["description"].as_str();
+            Some((
+                value.to_string(),
+                description.map(|description| description.replace('\n', " ")),
+            ))
+        })
+        .collect()
+}
+
+/// Object form of a schema that can take several forms, like ranges
+fn object_variant(schema: &Value) -> Option<&Value> {
+    std::iter::once(schema)
+        .chain(schema["oneOf"].as_array().into_iter().flatten())
+        .find(|schema| types(schema) == ["object"] && schema["properties"].is_object())
+}
+
+fn describe(variants: &[(String, Option<String>)]) -> Option<String> {
+    let lines: Vec<String> = variants
+        .iter()
+        .filter_map(|(name, description)| {
+            description
+                .as_ref()
+                .map(|description| format!("`{name}`: {description}"))
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Turns a camelCase variable into a label, like `mirrorPods` into `Mirror
+/// pods`
+fn label(variable: &str) -> String {
+    let mut label = String::new();
+    for (index, c) in variable.chars().enumerate() {
+        if index == 0 {
+            label.extend(c.to_uppercase());
+        } else if c.is_uppercase() {
+            label.push(' ');
+            label.extend(c.to_lowercase());
+        } else {
+            label.push(c);
+        }
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions_ui_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/questions-ui.yml");
+        let questions = generate();
+        if std::env::var_os("UPDATE_QUESTIONS_UI").is_some() {
+            std::fs::write(path, &questions).unwrap();
+        }
+
+        assert!(
+            std::fs::read_to_string(path).unwrap_or_default() == questions,
+            "questions-ui.yml is out of date, run `make questions-ui` to update it"
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(label("rule"), "Rule");
+        assert_eq!(label("mirrorPods"), "Mirror pods");
+        assert_eq!(
+            label("exemptPodAnnotationSelector"),
+            "Exempt pod annotation selector"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::settings::field_path;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+
+/// Same fields as `LabelSelector`, whose deserializer ignores unknown ones,
+/// so that a typo such as `matchLabel` is reported instead of leaving an
+/// empty selector, which matches every pod
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StrictLabelSelector {
+    #[serde(default)]
+    match_labels: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    match_expressions: Option<Vec<StrictLabelSelectorRequirement>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictLabelSelectorRequirement {
+    key: String,
+    operator: String,
+    #[serde(default)]
+    values: Option<Vec<String>>,
+}
+
+/// Deserializes an optional selector of the settings, rejecting unknown
+/// fields
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<LabelSelector>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let selector = Option::<StrictLabelSelector>::deserialize(deserializer)?;
+    Ok(selector.map(|selector| LabelSelector {
+        match_labels: selector.match_labels,
+        match_expressions: selector.match_expressions.map(|requirements| {
+            requirements
+                .into_iter()
+                .map(|requirement| LabelSelectorRequirement {
+                    key: requirement.key,
+                    operator: requirement.operator,
+                    values: requirement.values,
+                })
+                .collect()
+        }),
+    }))
+}
+
+/// Ensures the selector only uses the operators and values accepted by
+/// Kubernetes label selectors
+pub(crate) fn check(selector: &LabelSelector, path: &str, errors: &mut Vec<String>) {
+    for (index, requirement) in selector.match_expressions.iter().flatten().enumerate() {
+        let path = field_path(path, &format!("matchExpressions[{index}]"));
+        let has_values = requirement
+            .values
+            .as_ref()
+            .is_some_and(|values| !values.is_empty());
+        match requirement.operator.as_str() {
+            "In" | "NotIn" if !has_values => errors.push(format!(
+                "{}: operator {} requires at least one value",
+                field_path(&path, "values"),
+                requirement.operator
+            )),
+            "Exists" | "DoesNotExist" if has_values => errors.push(format!(
+                "{}: operator {} does not accept values",
+                field_path(&path, "values"),
+                requirement.operator
+            )),
+            "In" | "NotIn" | "Exists" | "DoesNotExist" => {}
+            operator => errors.push(format!(
+                "{}: unknown operator {operator}",
+                field_path(&path, "operator")
+            )),
+        }
+    }
+}
+
+/// Whether the selector has no requirement at all, and so matches everything
+pub(crate) fn is_empty(selector: &LabelSelector) -> bool {
+    selector
+        .match_labels
+        .as_ref()
+        .is_none_or(BTreeMap::is_empty)
+        && selector
+            .match_expressions
+            .as_ref()
+            .is_none_or(Vec::is_empty)
+}
+
+/// Evaluates the selector against a set of labels, or annotations. As with
+/// Kubernetes, an empty selector matches everything.
+pub(crate) fn matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let match_labels = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+
+    let match_expressions = selector
+        .match_expressions
+        .iter()
+        .flatten()
+        .all(|requirement| {
+            let value = labels.get(&requirement.key);
+            let values = requirement.values.as_deref().unwrap_or_default();
+            match requirement.operator.as_str() {
+                "In" => value.is_some_and(|value| values.contains(value)),
+                "NotIn" => !value.is_some_and(|value| values.contains(value)),
+                "Exists" => value.is_some(),
+                "DoesNotExist" => value.is_none(),
+                _ => false,
+            }
+        });
+
+    match_labels && match_expressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirement(key: &str, operator: &str, values: &[&str]) -> LabelSelectorRequirement {
+        LabelSelectorRequirement {
+            key: key.to_string(),
+            operator: operator.to_string(),
+            values: if values.is_empty() {
+                None
+            } else {
+                Some(values.iter().map(|value| value.to_string()).collect())
+            },
+        }
+    }
+
+    #[test]
+    fn selector_matches_labels() {
+        let labels: BTreeMap<String, String> = vec![
+            (
+                "app.kubernetes.io/part-of".to_string(),
+                "storage".to_string(),
+            ),
+            ("tier".to_string(), "backend".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(matches(&LabelSelector::default(), &labels));
+        assert!(matches(
+            &LabelSelector {
+                match_labels: Some(
+                    vec![(
+                        "app.kubernetes.io/part-of".to_string(),
+                        "storage".to_string()
+                    )]
+                    .into_iter()
+                    .collect()
+                ),
+                match_expressions: Some(vec![
+                    requirement("tier", "In", &["backend", "database"]),
+                    requirement("team", "NotIn", &["web"]),
+                    requirement("tier", "Exists", &[]),
+                    requirement("debug", "DoesNotExist", &[]),
+                ]),
+            },
+            &labels
+        ));
+
+        assert!(!matches(
+            &LabelSelector {
+                match_labels: Some(
+                    vec![("tier".to_string(), "frontend".to_string())]
+                        .into_iter()
+                        .collect()
+                ),
+                ..LabelSelector::default()
+            },
+            &labels
+        ));
+        assert!(!matches(
+            &LabelSelector {
+                match_expressions: Some(vec![requirement("tier", "NotIn", &["backend"])]),
+                ..LabelSelector::default()
+            },
+            &labels
+        ));
+        assert!(!matches(
+            &LabelSelector {
+                match_expressions: Some(vec![requirement("tier", "Equals", &["backend"])]),
+                ..LabelSelector::default()
+            },
+            &labels
+        ));
+    }
+
+    #[test]
+    fn valid_selector() {
+        let errors = |selector: &LabelSelector| {
+            let mut errors = Vec::new();
+            check(selector, "podSelector", &mut errors);
+            errors
+        };
+
+        assert_eq!(
+            errors(&LabelSelector {
+                match_expressions: Some(vec![
+                    requirement("tier", "In", &["backend"]),
+                    requirement("debug", "DoesNotExist", &[]),
+                ]),
+                ..LabelSelector::default()
+            }),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors(&LabelSelector {
+                match_expressions: Some(vec![
+                    requirement("tier", "In", &[]),
+                    requirement("tier", "Exists", &["backend"]),
+                    requirement("tier", "Equals", &["backend"]),
+                ]),
+                ..LabelSelector::default()
+            }),
+            vec![
+                "podSelector.matchExpressions[0].values: operator In requires at least one value"
+                    .to_string(),
+                "podSelector.matchExpressions[1].values: operator Exists does not accept values"
+                    .to_string(),
+                "podSelector.matchExpressions[2].operator: unknown operator Equals".to_string(),
+            ]
+        );
+    }
+}
@@ -1,74 +1,1092 @@
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
-use std::{fmt, fmt::Display};
+use crate::{glob, selector};
+use anyhow::Result;
+use k8s_openapi::api::core::v1 as apicore;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use kubewarden::request::UserInfo;
+#[cfg(feature = "schema")]
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+#[cfg(feature = "schema")]
+use std::borrow::Cow;
+use std::{convert::TryFrom, fmt, fmt::Display, str::FromStr};
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-#[serde(default)]
+/// Besides the canonical `{min, max}` form, ranges can be written as
+/// `"1000-1999"`, or as `"1000"` for a single value. Either bound can be
+/// omitted, as in `{min: 10000}` or `"10000-"`, in which case the range
+/// extends to the corresponding GID bound.
+#[derive(Serialize, Clone, Copy, Default, Debug, PartialEq)]
 pub(crate) struct Range {
     pub min: i64,
     pub max: i64,
 }
 
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RangeBounds {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
 impl Range {
-    fn check(&self) -> Result<()> {
-        if self.min > self.max {
-            return Err(anyhow!("min on range cannot be greater than max",));
+    fn from_bounds(min: Option<i64>, max: Option<i64>) -> Result<Range, String> {
+        if min.is_none() && max.is_none() {
+            return Err("a range must set min, max, or both".to_string());
+        }
+        Ok(Range {
+            min: min.unwrap_or(MIN_GID),
+            max: max.unwrap_or(MAX_GID),
+        })
+    }
+}
+
+impl FromStr for Range {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Range, String> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| format!("invalid range {range:?}, expected a form like \"1000-1999\""))
         };
-        Ok(())
+        // An empty bound leaves the range open on that side
+        let parse_bound = |value: &str| match value.trim() {
+            "" => Ok(None),
+            value => parse(value).map(Some),
+        };
+        match range.split_once('-') {
+            Some((min, max)) => Range::from_bounds(parse_bound(min)?, parse_bound(max)?),
+            None => {
+                let value = parse(range)?;
+                Ok(Range {
+                    min: value,
+                    max: value,
+                })
+            }
+        }
+    }
+}
+
+/// Formats the range in the string form, as `"1000-1999"`, or `"1000"` for a
+/// single value
+impl Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// Range as written in the settings, which remembers whether `min` was
+/// omitted, as `MustRunAs` cannot default the fsGroup from such a range
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(transparent)]
+struct RangeSpec {
+    range: Range,
+    #[serde(skip)]
+    min_omitted: bool,
+}
+
+impl FromStr for RangeSpec {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<RangeSpec, String> {
+        Ok(RangeSpec {
+            range: range.parse()?,
+            min_omitted: range
+                .split_once('-')
+                .is_some_and(|(min, _)| min.trim().is_empty()),
+        })
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "rule")]
+impl<'de> Deserialize<'de> for RangeSpec {
+    fn deserialize<D>(deserializer: D) -> Result<RangeSpec, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RangeVisitor;
+
+        impl<'de> Visitor<'de> for RangeVisitor {
+            type Value = RangeSpec;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a range with min and max, or a string like \"1000-1999\"")
+            }
+
+            fn visit_str<E: de::Error>(self, range: &str) -> Result<RangeSpec, E> {
+                range.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<RangeSpec, A::Error> {
+                let bounds = RangeBounds::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(RangeSpec {
+                    range: Range::from_bounds(bounds.min, bounds.max).map_err(de::Error::custom)?,
+                    min_omitted: bounds.min.is_none(),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RangeVisitor)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for RangeSpec {
+    fn schema_name() -> Cow<'static, str> {
+        Range::schema_name()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        Range::json_schema(generator)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for Range {
+    fn schema_name() -> Cow<'static, str> {
+        "Range".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut min = generator.subschema_for::<Gid>();
+        min.insert(
+            "description".to_string(),
+            format!("Lowest GID of the range, {MIN_GID} when omitted").into(),
+        );
+        let mut max = generator.subschema_for::<Gid>();
+        max.insert(
+            "description".to_string(),
+            format!("Highest GID of the range, {MAX_GID} when omitted").into(),
+        );
+        json_schema!({
+            "description": "Range of GIDs, either bound can be omitted to leave the range open on that side",
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "min": min,
+                        "max": max,
+                    },
+                    "minProperties": 1,
+                    "additionalProperties": false,
+                },
+                {
+                    "type": "string",
+                    "description": "Range in the \"min-max\" form, like \"1000-1999\", or a single GID",
+                    "pattern": "^ *[0-9]* *(- *[0-9]* *)?$",
+                },
+            ],
+        })
+    }
+}
+
+/// A single GID, as listed in the `values` setting
+struct Gid(i64);
+
+#[cfg(feature = "schema")]
+impl JsonSchema for Gid {
+    fn schema_name() -> Cow<'static, str> {
+        "Gid".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "integer",
+            "minimum": MIN_GID,
+            "maximum": MAX_GID,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Gid {
+    fn deserialize<D>(deserializer: D) -> Result<Gid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = i64::deserialize(deserializer)?;
+        if !(MIN_GID..=MAX_GID).contains(&value) {
+            return Err(de::Error::custom(format!(
+                "{value} is not a valid GID, it must be between {MIN_GID} and {MAX_GID}"
+            )));
+        }
+        Ok(Gid(value))
+    }
+}
+
+/// Lowest GID accepted by Kubernetes for `fsGroup`
+pub(crate) const MIN_GID: i64 = 0;
+/// Highest GID accepted by Kubernetes for `fsGroup`
+pub(crate) const MAX_GID: i64 = i32::MAX as i64;
+
+/// Joins the JSON path of a setting with the name of one of its fields
+pub(crate) fn field_path(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
+
+impl Range {
+    fn check(&self, path: &str, errors: &mut Vec<String>) {
+        let mut valid_bounds = true;
+        for (field, value) in [("min", self.min), ("max", self.max)] {
+            if !(MIN_GID..=MAX_GID).contains(&value) {
+                valid_bounds = false;
+                errors.push(format!(
+                    "{}: {value} is not a valid GID, it must be between {MIN_GID} and {MAX_GID}",
+                    field_path(path, field)
+                ));
+            }
+        }
+        // An out of bounds value may also invert an open-ended range, only
+        // the root cause is worth reporting
+        if valid_bounds && self.min > self.max {
+            errors.push(format!(
+                "{}: {} cannot be greater than max {}",
+                field_path(path, "min"),
+                self.min,
+                self.max
+            ));
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "rule", from = "RuleSpec")]
 pub(crate) enum Rule {
     MustRunAs(Ranges),
     MayRunAs(Ranges),
+    /// The ranges are forbidden, instead of allowed
+    MustNotRunAs(Ranges),
     RunAsAny,
 }
 
+/// Value of the `rule` setting
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+enum RuleKind {
+    /// The fsGroup must be included in one of the ranges, pods without
+    /// fsGroup are defaulted to the min value of the first range
+    MustRunAs,
+    /// The fsGroup, when set, must be included in one of the ranges
+    MayRunAs,
+    /// The fsGroup, when set, must not be included in any of the ranges
+    MustNotRunAs,
+    /// Any fsGroup is accepted
+    RunAsAny,
+}
+
+/// Rule as written by users under `fsGroup`
+#[derive(Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    schemars(
+        rename = "Rule",
+        description = "Rule applied to the fsGroup of the pod"
+    )
+)]
+struct RuleSpec {
+    rule: RuleKind,
+    /// Allowed GID ranges, or forbidden ones with MustNotRunAs
+    #[serde(default)]
+    ranges: Vec<RangeSpec>,
+    /// Single GIDs, added to the ranges
+    #[serde(default)]
+    values: Vec<Gid>,
+}
+
+/// The schema describes the rule as written by users
+#[cfg(feature = "schema")]
+impl JsonSchema for Rule {
+    fn schema_name() -> Cow<'static, str> {
+        RuleSpec::schema_name()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        RuleSpec::json_schema(generator)
+    }
+}
+
+impl From<RuleSpec> for Rule {
+    fn from(spec: RuleSpec) -> Rule {
+        Rule::new(spec.rule, spec.ranges, spec.values)
+    }
+}
+
+impl Rule {
+    /// Single `values` are appended to the list of ranges
+    fn new(kind: RuleKind, ranges: Vec<RangeSpec>, values: Vec<Gid>) -> Rule {
+        let first_min_omitted = ranges.first().is_some_and(|range| range.min_omitted);
+        let ranges = ranges
+            .into_iter()
+            .map(|spec| spec.range)
+            .chain(values.into_iter().map(|Gid(value)| Range {
+                min: value,
+                max: value,
+            }))
+            .collect();
+        match kind {
+            RuleKind::MustRunAs => Rule::MustRunAs(Ranges {
+                first_min_omitted,
+                ..Ranges::new(ranges)
+            }),
+            RuleKind::MayRunAs => Rule::MayRunAs(Ranges::new(ranges)),
+            RuleKind::MustNotRunAs => Rule::MustNotRunAs(Ranges::new(ranges)),
+            // Ranges have no meaning here, but UIs may still send an empty list
+            RuleKind::RunAsAny => Rule::RunAsAny,
+        }
+    }
+
+    fn redundancies(&self, path: &str) -> Vec<String> {
+        match self {
+            Rule::MustRunAs(ranges) | Rule::MayRunAs(ranges) | Rule::MustNotRunAs(ranges) => {
+                ranges.redundancies(path)
+            }
+            Rule::RunAsAny => Vec::new(),
+        }
+    }
+
+    fn check(&self, path: &str, errors: &mut Vec<String>) {
+        match self {
+            Rule::MustRunAs(ranges) | Rule::MayRunAs(ranges) | Rule::MustNotRunAs(ranges) => {
+                if ranges.ranges.is_empty() {
+                    errors.push(format!(
+                        "{}: {self} must contain at least one range",
+                        field_path(path, "ranges")
+                    ));
+                }
+                for (index, range) in ranges.ranges.iter().enumerate() {
+                    range.check(&field_path(path, &format!("ranges[{index}]")), errors);
+                }
+                // The default fsGroup would silently be 0
+                if matches!(self, Rule::MustRunAs(_)) && ranges.first_min_omitted {
+                    errors.push(format!(
+                        "{}: is required on the first range of MustRunAs, which is the default fsGroup",
+                        field_path(path, "ranges[0].min")
+                    ));
+                }
+            }
+            Rule::RunAsAny => {}
+        }
+    }
+}
+
 impl Display for Rule {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let rule = match *self {
             Rule::MustRunAs(_) => "MustRunAs",
             Rule::MayRunAs(_) => "MayRunAs",
+            Rule::MustNotRunAs(_) => "MustNotRunAs",
             Rule::RunAsAny => "RunAsAny",
         };
         write!(f, "{rule}")
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Clone, Default, Debug)]
 pub(crate) struct Ranges {
+    /// Ranges as written in the settings
     pub ranges: Vec<Range>,
+    /// Same ranges, sorted by `min`, with the overlapping and adjacent ones
+    /// merged together, computed once when the settings are loaded
+    #[serde(skip)]
+    normalized: Vec<Range>,
+    /// Whether the first range was written without `min`
+    #[serde(skip)]
+    first_min_omitted: bool,
+}
+
+impl Ranges {
+    pub fn new(ranges: Vec<Range>) -> Ranges {
+        let mut sorted = ranges.clone();
+        sorted.sort_by_key(|range| range.min);
+
+        let mut normalized: Vec<Range> = Vec::with_capacity(sorted.len());
+        for range in sorted {
+            match normalized.last_mut() {
+                Some(last) if range.min <= last.max.saturating_add(1) => {
+                    last.max = last.max.max(range.max);
+                }
+                _ => normalized.push(range),
+            }
+        }
+        Ranges {
+            ranges,
+            normalized,
+            first_min_omitted: false,
+        }
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.normalized()
+            .iter()
+            .any(|range| value >= range.min && value <= range.max)
+    }
+
+    /// Returns the ranges sorted by `min`, with the overlapping and adjacent
+    /// ones merged together
+    pub fn normalized(&self) -> &[Range] {
+        &self.normalized
+    }
+
+    /// Describes the ranges that are duplicated, overlap or are adjacent to
+    /// each other, and could be merged together
+    fn redundancies(&self, path: &str) -> Vec<String> {
+        let mut ranges: Vec<(usize, &Range)> = self.ranges.iter().enumerate().collect();
+        ranges.sort_by_key(|(index, range)| (range.min, range.max, *index));
+
+        let mut redundancies = Vec::new();
+        // Range reaching the highest value so far, among the ones already visited
+        let mut highest: Option<(usize, &Range)> = None;
+        for (index, range) in ranges {
+            if let Some((highest_index, highest_range)) = highest {
+                let (first, second) = (highest_index.min(index), highest_index.max(index));
+                let second_path = field_path(path, &format!("ranges[{second}]"));
+                if highest_range == range {
+                    redundancies.push(format!("{second_path}: duplicates ranges[{first}]"));
+                } else if range.min <= highest_range.max {
+                    redundancies.push(format!("{second_path}: overlaps ranges[{first}]"));
+                } else if range.min == highest_range.max.saturating_add(1) {
+                    redundancies.push(format!("{second_path}: is adjacent to ranges[{first}]"));
+                }
+            }
+            if highest.is_none_or(|(_, highest_range)| range.max > highest_range.max) {
+                highest = Some((index, range));
+            }
+        }
+        redundancies
+    }
+}
+
+/// Checks that do not reject the request, but attach a warning to the
+/// admission response when the pod is accepted with a risky configuration
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub(crate) enum AdvisoryCheck {
+    /// The pod runs with fsGroup 0
+    RootFsGroup,
+    /// The pod mounts PersistentVolumeClaims and has no fsGroup
+    MissingFsGroupWithPvc,
+    /// The fsGroup differs from the runAsGroup of the pod or of one of its containers
+    FsGroupRunAsGroupMismatch,
+}
+
+/// How to handle mirror pods, which the kubelet creates for static pods and
+/// which cannot be mutated
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub(crate) enum MirrorPods {
+    /// Accept mirror pods without validating them
+    Accept,
+    /// Accept mirror pods without validating them, with a warning
+    Warn,
+    /// Validate mirror pods, accepting the ones that would be mutated
+    #[default]
+    Validate,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ServiceAccount {
+    pub namespace: String,
+    pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Identities of the requesting user, as reported by `request.userInfo`, and
+/// of the service account the pod runs as
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct Subjects {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub service_accounts: Vec<ServiceAccount>,
+    /// `namespace/name` patterns matched against `spec.serviceAccountName`
+    /// of the pod, `*` and `?` wildcards are allowed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pod_service_accounts: Vec<String>,
+}
+
+impl Subjects {
+    fn is_empty(&self) -> bool {
+        self.users.is_empty()
+            && self.groups.is_empty()
+            && self.service_accounts.is_empty()
+            && self.pod_service_accounts.is_empty()
+    }
+
+    /// `pod_service_account` is the service account of the pod, in the
+    /// `namespace/name` form
+    pub fn matches(&self, user_info: &UserInfo, pod_service_account: &str) -> bool {
+        self.pod_service_accounts
+            .iter()
+            .any(|pattern| glob::matches(pattern, pod_service_account))
+            || self.users.contains(&user_info.username)
+            || self
+                .groups
+                .iter()
+                .any(|group| user_info.groups.contains(group))
+            || self.service_accounts.iter().any(|service_account| {
+                user_info.username
+                    == format!(
+                        "system:serviceaccount:{}:{}",
+                        service_account.namespace, service_account.name
+                    )
+            })
+    }
+}
+
+/// A rule that only applies to requests made by the given subjects, and to
+/// the pods matching the given selectors
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Profile {
+    pub subjects: Subjects,
+    /// Selector evaluated against the labels of the pod
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_selector: Option<LabelSelector>,
+    /// Selector evaluated against the annotations of the pod
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_annotation_selector: Option<LabelSelector>,
+    pub fs_group: Rule,
+}
+
+/// Profile as written by users, in any settings version. `#[serde(flatten)]`
+/// cannot be combined with `#[serde(deny_unknown_fields)]`, so the v1 rule
+/// fields are listed here.
+#[derive(Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    schemars(
+        rename = "Profile",
+        description = "Rule that only applies to requests made by the given subjects, and to the pods matching the given selectors"
+    )
+)]
+struct ProfileSpec {
+    #[serde(default)]
+    subjects: Subjects,
+    /// Selector evaluated against the labels of the pod
+    #[serde(default, deserialize_with = "selector::deserialize")]
+    pod_selector: Option<LabelSelector>,
+    /// Selector evaluated against the annotations of the pod
+    #[serde(default, deserialize_with = "selector::deserialize")]
+    pod_annotation_selector: Option<LabelSelector>,
+    /// Rule of the profile, apiVersion v1 only
+    rule: Option<RuleKind>,
+    /// Allowed GID ranges, or forbidden ones with MustNotRunAs, apiVersion v1 only
+    ranges: Option<Vec<RangeSpec>>,
+    /// Single GIDs, added to the ranges, apiVersion v1 only
+    values: Option<Vec<Gid>>,
+    /// Rule of the profile, apiVersion v2 only
+    fs_group: Option<Rule>,
+}
+
+impl ProfileSpec {
+    fn into_profile(
+        self,
+        api_version: ApiVersion,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) -> Profile {
+        Profile {
+            fs_group: api_version.rule(
+                path,
+                self.rule,
+                self.ranges,
+                self.values,
+                self.fs_group,
+                errors,
+            ),
+            subjects: self.subjects,
+            pod_selector: self.pod_selector,
+            pod_annotation_selector: self.pod_annotation_selector,
+        }
+    }
+}
+
+impl Profile {
+    /// A profile without subjects applies to every request, as long as the
+    /// pod matches its selectors
+    pub fn matches(
+        &self,
+        user_info: &UserInfo,
+        pod_service_account: &str,
+        pod_metadata: &ObjectMeta,
+    ) -> bool {
+        (self.subjects.is_empty() || self.subjects.matches(user_info, pod_service_account))
+            && selectors_match(
+                self.pod_selector.as_ref(),
+                self.pod_annotation_selector.as_ref(),
+                pod_metadata,
+            )
+    }
+
+    fn check(&self, api_version: ApiVersion, path: &str, errors: &mut Vec<String>) {
+        if self.subjects.is_empty()
+            && self.pod_selector.is_none()
+            && self.pod_annotation_selector.is_none()
+        {
+            errors.push(format!(
+                "{path}: must contain at least one subject or selector"
+            ));
+        }
+        if let Some(pod_selector) = &self.pod_selector {
+            selector::check(pod_selector, &field_path(path, "podSelector"), errors);
+        }
+        if let Some(pod_annotation_selector) = &self.pod_annotation_selector {
+            selector::check(
+                pod_annotation_selector,
+                &field_path(path, "podAnnotationSelector"),
+                errors,
+            );
+        }
+        self.fs_group.check(&api_version.rule_path(path), errors);
+    }
+}
+
+/// Evaluates the optional label and annotation selectors against the pod
+/// metadata. Selectors that are not set match every pod.
+fn selectors_match(
+    pod_selector: Option<&LabelSelector>,
+    pod_annotation_selector: Option<&LabelSelector>,
+    pod_metadata: &ObjectMeta,
+) -> bool {
+    let empty = Default::default();
+    pod_selector.is_none_or(|pod_selector| {
+        selector::matches(pod_selector, pod_metadata.labels.as_ref().unwrap_or(&empty))
+    }) && pod_annotation_selector.is_none_or(|pod_annotation_selector| {
+        selector::matches(
+            pod_annotation_selector,
+            pod_metadata.annotations.as_ref().unwrap_or(&empty),
+        )
+    })
+}
+
+/// Pods that are not validated at all
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct Exemptions {
+    /// Pods whose labels match this selector are not validated
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "selector::deserialize"
+    )]
+    pub pod_selector: Option<LabelSelector>,
+    /// Pods whose annotations match this selector are not validated
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "selector::deserialize"
+    )]
+    pub pod_annotation_selector: Option<LabelSelector>,
+    /// Image patterns, `*` and `?` wildcards are allowed. Pods whose
+    /// container, init container and ephemeral container images all match
+    /// one of them are not validated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+impl Exemptions {
+    fn is_empty(&self) -> bool {
+        self.pod_selector.is_none()
+            && self.pod_annotation_selector.is_none()
+            && self.images.is_empty()
+    }
+
+    /// A pod is exempt when it matches all the exemption selectors, and at
+    /// least one of them is set, or when all its images are exempt
+    fn matches(&self, pod: &apicore::Pod) -> bool {
+        let exempt_by_selectors = (self.pod_selector.is_some()
+            || self.pod_annotation_selector.is_some())
+            && selectors_match(
+                self.pod_selector.as_ref(),
+                self.pod_annotation_selector.as_ref(),
+                &pod.metadata,
+            );
+        exempt_by_selectors || self.has_exempt_images(pod)
+    }
+
+    fn has_exempt_images(&self, pod: &apicore::Pod) -> bool {
+        let Some(pod_spec) = &pod.spec else {
+            return false;
+        };
+        let mut images = pod_spec
+            .containers
+            .iter()
+            .map(|container| container.image.as_deref())
+            .chain(
+                pod_spec
+                    .init_containers
+                    .iter()
+                    .flatten()
+                    .map(|container| container.image.as_deref()),
+            )
+            .chain(
+                pod_spec
+                    .ephemeral_containers
+                    .iter()
+                    .flatten()
+                    .map(|container| container.image.as_deref()),
+            )
+            .peekable();
+
+        !self.images.is_empty()
+            && images.peek().is_some()
+            && images.all(|image| {
+                let image = image.unwrap_or_default();
+                self.images
+                    .iter()
+                    .any(|pattern| glob::matches(pattern, image))
+            })
+    }
+}
+
+/// Version of the settings format. v1, the default, flattens the rule into
+/// the settings and lists the exemptions at the top level. v2 nests the rule
+/// under `fsGroup` and groups the exemptions under `exemptions`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub(crate) enum ApiVersion {
+    #[default]
+    #[serde(rename = "v1")]
+    V1,
+    #[serde(rename = "v2")]
+    V2,
+}
+
+impl ApiVersion {
+    /// Builds the rule out of the fields of this version, reporting the ones
+    /// that belong to the other version
+    fn rule(
+        self,
+        path: &str,
+        rule: Option<RuleKind>,
+        ranges: Option<Vec<RangeSpec>>,
+        values: Option<Vec<Gid>>,
+        fs_group: Option<Rule>,
+        errors: &mut Vec<String>,
+    ) -> Rule {
+        match self {
+            ApiVersion::V1 => {
+                if fs_group.is_some() {
+                    errors.push(format!(
+                        "{}: requires apiVersion v2",
+                        field_path(path, "fsGroup")
+                    ));
+                }
+                match rule {
+                    Some(rule) => {
+                        Rule::new(rule, ranges.unwrap_or_default(), values.unwrap_or_default())
+                    }
+                    None => {
+                        errors.push(format!("{}: is required", field_path(path, "rule")));
+                        Rule::RunAsAny
+                    }
+                }
+            }
+            ApiVersion::V2 => {
+                let v1_fields = [
+                    ("rule", rule.is_some()),
+                    ("ranges", ranges.is_some()),
+                    ("values", values.is_some()),
+                ];
+                for (field, _) in v1_fields.iter().filter(|(_, present)| *present) {
+                    errors.push(format!(
+                        "{}: is not supported by apiVersion v2, set it under fsGroup",
+                        field_path(path, field)
+                    ));
+                }
+                fs_group.unwrap_or_else(|| {
+                    errors.push(format!("{}: is required", field_path(path, "fsGroup")));
+                    Rule::RunAsAny
+                })
+            }
+        }
+    }
+
+    /// Path of the rule fields inside the object at `path`
+    fn rule_path(self, path: &str) -> String {
+        match self {
+            ApiVersion::V1 => path.to_string(),
+            ApiVersion::V2 => field_path(path, "fsGroup"),
+        }
+    }
+
+    /// Path of the exemption `field`, like `podSelector`
+    fn exemption_path(self, field: &str) -> String {
+        match self {
+            ApiVersion::V1 => format!("exempt{}{}", field[..1].to_ascii_uppercase(), &field[1..]),
+            ApiVersion::V2 => field_path("exemptions", field),
+        }
+    }
+}
+
+/// Settings are always serialized in the latest version. Older versions are
+/// upgraded on deserialization.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(
+    rename_all = "camelCase",
+    tag = "apiVersion",
+    rename = "v2",
+    try_from = "SettingsSpec"
+)]
 pub(crate) struct Settings {
-    #[serde(flatten)]
-    pub rule: Rule,
+    /// Version the settings were written in, used to report errors with the
+    /// paths found in the original settings
+    #[serde(skip)]
+    pub api_version: ApiVersion,
+    pub fs_group: Rule,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub advisory_checks: Vec<AdvisoryCheck>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    #[serde(skip_serializing_if = "Exemptions::is_empty")]
+    pub exemptions: Exemptions,
+    pub mirror_pods: MirrorPods,
+    /// Reject settings with duplicated, overlapping or adjacent ranges,
+    /// instead of just warning about them
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub strict_ranges: bool,
+}
+
+/// Settings as written by users, in any version. `#[serde(flatten)]` cannot
+/// be combined with `#[serde(deny_unknown_fields)]`, so the fields of every
+/// version are listed here, and checked against `apiVersion` afterwards.
+#[derive(Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(
+    feature = "schema",
+    schemars(
+        rename = "Settings",
+        description = "Settings of the allowed-fsgroups-psp policy"
+    )
+)]
+struct SettingsSpec {
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    api_version: ApiVersion,
+    /// Rule applied to the fsGroup of the pod
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"label": "fsGroup rule", "default": "RunAsAny"})))]
+    rule: Option<RuleKind>,
+    /// Allowed GID ranges, or forbidden ones with MustNotRunAs
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"label": "GID ranges", "show_if": "rule=MustRunAs||rule=MayRunAs||rule=MustNotRunAs"})))]
+    ranges: Option<Vec<RangeSpec>>,
+    /// Single GIDs, added to the ranges
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"label": "Single GIDs", "show_if": "rule=MustRunAs||rule=MayRunAs||rule=MustNotRunAs"})))]
+    values: Option<Vec<Gid>>,
+    /// Rule applied to the fsGroup of the pod, replaces rule, ranges and
+    /// values in apiVersion v2
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    fs_group: Option<Rule>,
+    /// Checks that attach a warning to the admission response of accepted pods
+    #[serde(default)]
+    advisory_checks: Vec<AdvisoryCheck>,
+    /// Rules for specific subjects and pods, used instead of the top level rule
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    profiles: Vec<ProfileSpec>,
+    /// Pods whose labels match this selector are not validated
+    #[serde(default, deserialize_with = "selector::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    exempt_pod_selector: Option<LabelSelector>,
+    /// Pods whose annotations match this selector are not validated
+    #[serde(default, deserialize_with = "selector::deserialize")]
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    exempt_pod_annotation_selector: Option<LabelSelector>,
+    /// Image patterns, `*` and `?` wildcards are allowed. Pods whose images
+    /// all match one of them are not validated.
+    exempt_images: Option<Vec<String>>,
+    /// Pods that are not validated, replaces exemptPodSelector,
+    /// exemptPodAnnotationSelector and exemptImages in apiVersion v2
+    #[cfg_attr(feature = "schema", schemars(extend("x-ui" = {"hidden": true})))]
+    exemptions: Option<Exemptions>,
+    /// How to handle mirror pods, which the kubelet creates for static pods
+    /// and which cannot be mutated
+    #[serde(default)]
+    mirror_pods: MirrorPods,
+    /// Reject settings with duplicated, overlapping or adjacent ranges,
+    /// instead of logging a warning
+    #[serde(default)]
+    strict_ranges: bool,
+}
+
+/// The schema describes the settings as written by users, in any version
+#[cfg(feature = "schema")]
+impl JsonSchema for Settings {
+    fn schema_name() -> Cow<'static, str> {
+        SettingsSpec::schema_name()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        SettingsSpec::json_schema(generator)
+    }
+}
+
+impl TryFrom<SettingsSpec> for Settings {
+    type Error = String;
+
+    fn try_from(spec: SettingsSpec) -> Result<Settings, String> {
+        let api_version = spec.api_version;
+        let mut errors = Vec::new();
+        let fs_group = api_version.rule(
+            "",
+            spec.rule,
+            spec.ranges,
+            spec.values,
+            spec.fs_group,
+            &mut errors,
+        );
+        let profiles = spec
+            .profiles
+            .into_iter()
+            .enumerate()
+            .map(|(index, profile)| {
+                profile.into_profile(api_version, &format!("profiles[{index}]"), &mut errors)
+            })
+            .collect();
+        let exemptions = match api_version {
+            ApiVersion::V1 => {
+                if spec.exemptions.is_some() {
+                    errors.push("exemptions: requires apiVersion v2".to_string());
+                }
+                Exemptions {
+                    pod_selector: spec.exempt_pod_selector,
+                    pod_annotation_selector: spec.exempt_pod_annotation_selector,
+                    images: spec.exempt_images.unwrap_or_default(),
+                }
+            }
+            ApiVersion::V2 => {
+                let v1_fields = [
+                    ("exemptPodSelector", spec.exempt_pod_selector.is_some()),
+                    (
+                        "exemptPodAnnotationSelector",
+                        spec.exempt_pod_annotation_selector.is_some(),
+                    ),
+                    ("exemptImages", spec.exempt_images.is_some()),
+                ];
+                for (field, _) in v1_fields.iter().filter(|(_, present)| *present) {
+                    errors.push(format!(
+                        "{field}: is not supported by apiVersion v2, set it under exemptions"
+                    ));
+                }
+                spec.exemptions.unwrap_or_default()
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        Ok(Settings {
+            api_version,
+            fs_group,
+            advisory_checks: spec.advisory_checks,
+            profiles,
+            exemptions,
+            mirror_pods: spec.mirror_pods,
+            strict_ranges: spec.strict_ranges,
+        })
+    }
+}
+
+impl Settings {
+    /// Deserializes the settings, prefixing errors with the JSON path of the
+    /// offending setting
+    pub fn from_slice(payload: &[u8]) -> Result<Settings, String> {
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_slice(payload))
+            .map_err(|error| match error.path().to_string().as_str() {
+                "." => error.inner().to_string(),
+                path => format!("{path}: {}", error.inner()),
+            })
+    }
+
+    /// Describes the ranges of the rule and of the profiles that could be
+    /// merged together
+    pub fn range_warnings(&self) -> Vec<String> {
+        let mut warnings = self.fs_group.redundancies(&self.api_version.rule_path(""));
+        for (index, profile) in self.profiles.iter().enumerate() {
+            warnings.extend(
+                profile
+                    .fs_group
+                    .redundancies(&self.api_version.rule_path(&format!("profiles[{index}]"))),
+            );
+        }
+        warnings
+    }
+
+    pub fn is_exempt(&self, pod: &apicore::Pod) -> bool {
+        self.exemptions.matches(pod)
+    }
 }
 
 impl Default for Settings {
     fn default() -> Settings {
         Settings {
-            rule: Rule::RunAsAny,
+            api_version: ApiVersion::default(),
+            fs_group: Rule::RunAsAny,
+            advisory_checks: Vec::new(),
+            profiles: Vec::new(),
+            exemptions: Exemptions::default(),
+            mirror_pods: MirrorPods::default(),
+            strict_ranges: false,
         }
     }
 }
 
 impl kubewarden::settings::Validatable for Settings {
+    /// Reports every invalid setting at once, each error prefixed by the
+    /// JSON path of the setting
     fn validate(&self) -> Result<(), String> {
-        match &self.rule {
-            Rule::MustRunAs(ranges) | Rule::MayRunAs(ranges) => {
-                if ranges.ranges.is_empty() {
-                    return Err(format!("{} must contain at least one range", self.rule));
-                }
-                if !ranges.ranges.iter().all(|range| range.check().is_ok()) {
-                    return Err("all ranges must be valid".to_string());
-                }
-                Ok(())
+        let mut errors = Vec::new();
+        self.fs_group
+            .check(&self.api_version.rule_path(""), &mut errors);
+        let exemption_selectors = [
+            ("podSelector", &self.exemptions.pod_selector),
+            (
+                "podAnnotationSelector",
+                &self.exemptions.pod_annotation_selector,
+            ),
+        ];
+        for (field, exemption_selector) in exemption_selectors.iter() {
+            let Some(exemption_selector) = exemption_selector else {
+                continue;
+            };
+            let path = self.api_version.exemption_path(field);
+            // An empty selector matches every pod, and would exempt them all
+            if selector::is_empty(exemption_selector) {
+                errors.push(format!(
+                    "{path}: must set matchLabels or matchExpressions, an empty selector \
+                     would exempt every pod"
+                ));
             }
-            Rule::RunAsAny => Ok(()),
+            selector::check(exemption_selector, &path, &mut errors);
+        }
+        for (index, profile) in self.profiles.iter().enumerate() {
+            profile.check(self.api_version, &format!("profiles[{index}]"), &mut errors);
+        }
+        if self.strict_ranges {
+            errors.extend(self.range_warnings());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
         }
     }
 }
@@ -83,20 +1101,20 @@ mod tests {
     fn valid_settings() -> Result<()> {
         assert_eq!(
             Settings {
-                rule: Rule::MayRunAs(Ranges { ranges: Vec::new() })
+                fs_group: Rule::MayRunAs(Ranges::new(Vec::new())),
+                ..Settings::default()
             }
             .validate(),
-            Err("MayRunAs must contain at least one range".to_string())
+            Err("ranges: MayRunAs must contain at least one range".to_string())
         );
 
         assert_eq!(
             Settings {
-                rule: Rule::MayRunAs(Ranges {
-                    ranges: vec![Range {
-                        min: 1000,
-                        max: 1000
-                    }],
-                })
+                fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                    min: 1000,
+                    max: 1000
+                }])),
+                ..Settings::default()
             }
             .validate(),
             Ok(())
@@ -104,51 +1122,787 @@ mod tests {
 
         assert_eq!(
             Settings {
-                rule: Rule::MayRunAs(Ranges {
-                    ranges: vec![Range {
-                        min: 1000,
-                        max: 500
-                    }],
-                })
+                fs_group: Rule::MayRunAs(Ranges::new(vec![Range {
+                    min: 1000,
+                    max: 500
+                }])),
+                ..Settings::default()
             }
             .validate(),
-            Err("all ranges must be valid".to_string())
+            Err("ranges[0].min: 1000 cannot be greater than max 500".to_string())
         );
 
         assert_eq!(
             Settings {
-                rule: Rule::MustRunAs(Ranges { ranges: Vec::new() })
+                fs_group: Rule::MustRunAs(Ranges::new(Vec::new())),
+                ..Settings::default()
             }
             .validate(),
-            Err("MustRunAs must contain at least one range".to_string())
+            Err("ranges: MustRunAs must contain at least one range".to_string())
         );
 
         assert_eq!(
             Settings {
-                rule: Rule::MustRunAs(Ranges {
-                    ranges: vec![Range {
+                fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                    min: 1000,
+                    max: 1000
+                }])),
+                ..Settings::default()
+            }
+            .validate(),
+            Ok(())
+        );
+
+        assert_eq!(
+            Settings {
+                fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
+                    min: 1000,
+                    max: 500
+                }])),
+                ..Settings::default()
+            }
+            .validate(),
+            Err("ranges[0].min: 1000 cannot be greater than max 500".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ranges_within_gid_bounds() {
+        let settings = |min, max| Settings {
+            fs_group: Rule::MustRunAs(Ranges::new(vec![
+                Range {
+                    min: 1000,
+                    max: 2000,
+                },
+                Range { min, max },
+            ])),
+            ..Settings::default()
+        };
+
+        assert_eq!(settings(MIN_GID, MAX_GID).validate(), Ok(()));
+        assert_eq!(
+            settings(-1, 1000).validate(),
+            Err(
+                "ranges[1].min: -1 is not a valid GID, it must be between 0 and 2147483647"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            settings(1000, MAX_GID + 1).validate(),
+            Err(
+                "ranges[1].max: 2147483648 is not a valid GID, it must be between 0 and 2147483647"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn valid_profiles() -> Result<()> {
+        assert_eq!(
+            Settings {
+                profiles: vec![Profile {
+                    subjects: Subjects::default(),
+                    pod_selector: None,
+                    pod_annotation_selector: None,
+                    fs_group: Rule::RunAsAny,
+                }],
+                ..Settings::default()
+            }
+            .validate(),
+            Err("profiles[0]: must contain at least one subject or selector".to_string())
+        );
+
+        assert_eq!(
+            Settings {
+                profiles: vec![Profile {
+                    subjects: Subjects {
+                        groups: vec!["storage-admins".to_string()],
+                        ..Subjects::default()
+                    },
+                    pod_selector: None,
+                    pod_annotation_selector: None,
+                    fs_group: Rule::MustRunAs(Ranges::new(Vec::new())),
+                }],
+                ..Settings::default()
+            }
+            .validate(),
+            Err("profiles[0].ranges: MustRunAs must contain at least one range".to_string())
+        );
+
+        assert_eq!(
+            Settings {
+                profiles: vec![Profile {
+                    subjects: Subjects {
+                        groups: vec!["storage-admins".to_string()],
+                        ..Subjects::default()
+                    },
+                    pod_selector: None,
+                    pod_annotation_selector: None,
+                    fs_group: Rule::MustRunAs(Ranges::new(vec![Range {
                         min: 1000,
                         max: 1000
-                    }],
-                })
+                    }])),
+                }],
+                ..Settings::default()
             }
             .validate(),
             Ok(())
         );
 
+        Ok(())
+    }
+
+    #[test]
+    fn subjects_match_user_info() {
+        let subjects = Subjects {
+            users: vec!["alice".to_string()],
+            groups: vec!["storage-admins".to_string()],
+            service_accounts: vec![ServiceAccount {
+                namespace: "backup".to_string(),
+                name: "velero".to_string(),
+            }],
+            pod_service_accounts: vec!["storage/*-agent".to_string()],
+        };
+
+        assert!(subjects.matches(
+            &UserInfo {
+                username: "alice".to_string(),
+                ..UserInfo::default()
+            },
+            "default/default"
+        ));
+        assert!(subjects.matches(
+            &UserInfo {
+                username: "bob".to_string(),
+                groups: vec!["storage-admins".to_string()].into_iter().collect(),
+                ..UserInfo::default()
+            },
+            "default/default"
+        ));
+        assert!(subjects.matches(
+            &UserInfo {
+                username: "system:serviceaccount:backup:velero".to_string(),
+                ..UserInfo::default()
+            },
+            "default/default"
+        ));
+        assert!(subjects.matches(
+            &UserInfo {
+                username: "system:serviceaccount:kube-system:replicaset-controller".to_string(),
+                ..UserInfo::default()
+            },
+            "storage/backup-agent"
+        ));
+        assert!(!subjects.matches(
+            &UserInfo {
+                username: "system:serviceaccount:default:velero".to_string(),
+                ..UserInfo::default()
+            },
+            "storage/backup"
+        ));
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        let invalid_selector = || LabelSelector {
+            match_expressions: Some(vec![
+                k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement {
+                    key: "tier".to_string(),
+                    operator: "Equals".to_string(),
+                    values: Some(vec!["backend".to_string()]),
+                },
+            ]),
+            ..LabelSelector::default()
+        };
+
         assert_eq!(
             Settings {
-                rule: Rule::MustRunAs(Ranges {
-                    ranges: vec![Range {
+                exemptions: Exemptions {
+                    pod_annotation_selector: Some(invalid_selector()),
+                    ..Exemptions::default()
+                },
+                ..Settings::default()
+            }
+            .validate(),
+            Err(
+                "exemptPodAnnotationSelector.matchExpressions[0].operator: unknown operator Equals"
+                    .to_string()
+            )
+        );
+
+        assert_eq!(
+            Settings {
+                profiles: vec![Profile {
+                    subjects: Subjects::default(),
+                    pod_selector: Some(invalid_selector()),
+                    pod_annotation_selector: None,
+                    fs_group: Rule::RunAsAny,
+                }],
+                ..Settings::default()
+            }
+            .validate(),
+            Err(
+                "profiles[0].podSelector.matchExpressions[0].operator: unknown operator Equals"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn empty_exemption_selectors_are_rejected() {
+        let validate = |settings: &str| {
+            Settings::from_slice(settings.as_bytes())
+                .unwrap()
+                .validate()
+        };
+
+        assert_eq!(
+            validate(r#"{"rule": "RunAsAny", "exemptPodSelector": {}}"#),
+            Err(
+                "exemptPodSelector: must set matchLabels or matchExpressions, an empty selector \
+                 would exempt every pod"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            validate(
+                r#"{"apiVersion": "v2", "fsGroup": {"rule": "RunAsAny"},
+                    "exemptions": {"podAnnotationSelector": {"matchLabels": {}, "matchExpressions": []}}}"#
+            ),
+            Err(
+                "exemptions.podAnnotationSelector: must set matchLabels or matchExpressions, an \
+                 empty selector would exempt every pod"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            validate(
+                r#"{"rule": "RunAsAny", "exemptPodSelector": {"matchLabels": {"app": "storage"}}}"#
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn exempt_images() {
+        let settings = Settings {
+            exemptions: Exemptions {
+                images: vec![
+                    "registry.example.com/storage/*".to_string(),
+                    "docker.io/velero/velero:v1.*".to_string(),
+                ],
+                ..Exemptions::default()
+            },
+            ..Settings::default()
+        };
+        let pod = |images: &[&str]| apicore::Pod {
+            spec: Some(apicore::PodSpec {
+                containers: images
+                    .iter()
+                    .map(|image| apicore::Container {
+                        image: Some(image.to_string()),
+                        ..apicore::Container::default()
+                    })
+                    .collect(),
+                init_containers: Some(vec![apicore::Container {
+                    image: Some("docker.io/velero/velero:v1.14.0".to_string()),
+                    ..apicore::Container::default()
+                }]),
+                ..apicore::PodSpec::default()
+            }),
+            ..apicore::Pod::default()
+        };
+
+        assert!(settings.is_exempt(&pod(&["registry.example.com/storage/agent:1.0"])));
+        assert!(!settings.is_exempt(&pod(&["registry.example.com/storage/agent:1.0", "nginx"])));
+        assert!(!Settings::default().is_exempt(&pod(&["nginx"])));
+    }
+
+    #[test]
+    fn normalized_ranges() {
+        let ranges = Ranges::new(vec![
+            Range {
+                min: 3000,
+                max: 4000,
+            },
+            Range { min: 100, max: 200 },
+            Range {
+                min: 1000,
+                max: 2000,
+            },
+            Range {
+                min: 1500,
+                max: 2500,
+            },
+            Range {
+                min: 2501,
+                max: 2600,
+            },
+            Range { min: 100, max: 200 },
+        ]);
+
+        assert_eq!(
+            ranges.normalized(),
+            vec![
+                Range { min: 100, max: 200 },
+                Range {
+                    min: 1000,
+                    max: 2600,
+                },
+                Range {
+                    min: 3000,
+                    max: 4000,
+                },
+            ]
+        );
+        assert_eq!(
+            ranges.redundancies(""),
+            vec![
+                "ranges[5]: duplicates ranges[1]".to_string(),
+                "ranges[3]: overlaps ranges[2]".to_string(),
+                "ranges[4]: is adjacent to ranges[3]".to_string(),
+            ]
+        );
+        assert!(ranges.contains(2550));
+        assert!(!ranges.contains(2700));
+
+        // The ranges of the settings are normalized once, when loaded
+        let settings = Settings::from_slice(
+            br#"{"rule": "MustRunAs", "ranges": ["3000-4000", "1000-2000", "2001-2500"]}"#,
+        )
+        .unwrap();
+        match &settings.fs_group {
+            Rule::MustRunAs(ranges) => assert_eq!(
+                ranges.normalized(),
+                [
+                    Range {
                         min: 1000,
-                        max: 500
-                    }],
-                })
+                        max: 2500,
+                    },
+                    Range {
+                        min: 3000,
+                        max: 4000,
+                    },
+                ]
+            ),
+            rule => panic!("unexpected rule {}", rule),
+        }
+    }
+
+    #[test]
+    fn strict_ranges() {
+        let settings = |strict_ranges| Settings {
+            fs_group: Rule::MayRunAs(Ranges::new(vec![
+                Range {
+                    min: 1000,
+                    max: 2000,
+                },
+                Range {
+                    min: 2000,
+                    max: 3000,
+                },
+            ])),
+            profiles: vec![Profile {
+                subjects: Subjects {
+                    users: vec!["alice".to_string()],
+                    ..Subjects::default()
+                },
+                pod_selector: None,
+                pod_annotation_selector: None,
+                fs_group: Rule::MustRunAs(Ranges::new(vec![
+                    Range { min: 0, max: 10 },
+                    Range { min: 0, max: 10 },
+                ])),
+            }],
+            strict_ranges,
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings(false).range_warnings(),
+            vec![
+                "ranges[1]: overlaps ranges[0]".to_string(),
+                "profiles[0].ranges[1]: duplicates ranges[0]".to_string(),
+            ]
+        );
+        assert_eq!(settings(false).validate(), Ok(()));
+        assert_eq!(
+            settings(true).validate(),
+            Err(
+                "ranges[1]: overlaps ranges[0]; profiles[0].ranges[1]: duplicates ranges[0]"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        assert_eq!(
+            Settings {
+                fs_group: Rule::MustRunAs(Ranges::new(vec![
+                    Range { min: -1, max: 10 },
+                    Range {
+                        min: 2000,
+                        max: 1000,
+                    },
+                ])),
+                profiles: vec![Profile {
+                    subjects: Subjects::default(),
+                    pod_selector: None,
+                    pod_annotation_selector: None,
+                    fs_group: Rule::MayRunAs(Ranges::new(Vec::new())),
+                }],
+                ..Settings::default()
             }
             .validate(),
-            Err("all ranges must be valid".to_string())
+            Err([
+                "ranges[0].min: -1 is not a valid GID, it must be between 0 and 2147483647",
+                "ranges[1].min: 2000 cannot be greater than max 1000",
+                "profiles[0]: must contain at least one subject or selector",
+                "profiles[0].ranges: MayRunAs must contain at least one range",
+            ]
+            .join("; "))
         );
+    }
 
-        Ok(())
+    #[test]
+    fn deserialization_errors_carry_the_path() {
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "RunAsAny", "exemptImages": ["nginx", 1]}"#)
+                .unwrap_err(),
+            "exemptImages[1]: invalid type: integer `1`, expected a string at line 1 column 48"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "RunAsAny", "profiles": [{"subjects": {"users": "alice"}, "rule": "RunAsAny"}]}"#
+            )
+            .unwrap_err(),
+            "profiles[0].subjects.users: invalid type: string \"alice\", expected a sequence at line 1 column 64"
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MustRunAs", "range": [{"min": 1000, "max": 2000}]}"#)
+                .unwrap_err(),
+            "range: unknown field `range`, expected one of `apiVersion`, `rule`, `ranges`, `values`, `fsGroup`, `advisoryChecks`, `profiles`, `exemptPodSelector`, `exemptPodAnnotationSelector`, `exemptImages`, `exemptions`, `mirrorPods`, `strictRanges` at line 1 column 29"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "MustRunAs", "ranges": [{"mn": 1000, "max": 2000}]}"#
+            )
+            .unwrap_err(),
+            "ranges[0].mn: unknown field `mn`, expected `min` or `max` at line 1 column 38"
+        );
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MustRunAs", "ranges": [{}]}"#).unwrap_err(),
+            "ranges[0]: a range must set min, max, or both at line 1 column 35"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "RunAsAny", "profiles": [{"subject": {"users": ["alice"]}, "rule": "RunAsAny"}]}"#
+            )
+            .unwrap_err(),
+            "profiles[0].subject: unknown field `subject`, expected one of `subjects`, `podSelector`, `podAnnotationSelector`, `rule`, `ranges`, `values`, `fsGroup` at line 1 column 44"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "MustRunAs", "ranges": ["1000-2000"], "exemptPodSelector": {"matchLabel": {"app": "storage"}}}"#
+            )
+            .unwrap_err(),
+            "exemptPodSelector.matchLabel: unknown field `matchLabel`, expected `matchLabels` or `matchExpressions` at line 1 column 81"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"apiVersion": "v2", "fsGroup": {"rule": "RunAsAny"}, "exemptions": {"podSelector": {"matchExpressions": [{"key": "app", "operator": "Exists", "value": ["storage"]}]}}}"#
+            )
+            .unwrap_err(),
+            "exemptions.podSelector.matchExpressions[0].value: unknown field `value`, expected one of `key`, `operator`, `values` at line 1 column 150"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "RunAsAny", "profiles": [{"subjects": {"users": ["alice"]}, "podSelector": {"matchLabel": {"app": "storage"}}, "rule": "RunAsAny"}]}"#
+            )
+            .unwrap_err(),
+            "profiles[0].podSelector.matchLabel: unknown field `matchLabel`, expected `matchLabels` or `matchExpressions` at line 1 column 97"
+        );
+    }
+
+    #[test]
+    fn settings_deserialization() {
+        let settings = Settings::from_slice(
+            br#"{"rule": "MayRunAs", "ranges": [{"min": 1000, "max": 2000}], "profiles": [{"subjects": {"groups": ["storage-admins"]}, "rule": "RunAsAny", "ranges": []}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::json!({
+                "apiVersion": "v2",
+                "fsGroup": {"rule": "MayRunAs", "ranges": [{"min": 1000, "max": 2000}]},
+                "profiles": [{"subjects": {"groups": ["storage-admins"]}, "fsGroup": {"rule": "RunAsAny"}}],
+                "mirrorPods": "Validate",
+            })
+        );
+    }
+
+    #[test]
+    fn shorthand_ranges() {
+        let settings = Settings::from_slice(
+            br#"{"rule": "MustRunAs", "ranges": ["3000-3999", {"min": 1000, "max": 1999}, "5000"], "values": [100, 200]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&settings.fs_group).unwrap(),
+            serde_json::json!({
+                "rule": "MustRunAs",
+                "ranges": [
+                    {"min": 3000, "max": 3999},
+                    {"min": 1000, "max": 1999},
+                    {"min": 5000, "max": 5000},
+                    {"min": 100, "max": 100},
+                    {"min": 200, "max": 200},
+                ],
+            })
+        );
+
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MustRunAs", "ranges": ["1000..1999"]}"#)
+                .unwrap_err(),
+            "ranges[0]: invalid range \"1000..1999\", expected a form like \"1000-1999\" at line 1 column 45"
+        );
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MustRunAs", "values": [1000, -1]}"#).unwrap_err(),
+            "values[1]: -1 is not a valid GID, it must be between 0 and 2147483647 at line 1 column 42"
+        );
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        let settings = Settings::from_slice(
+            br#"{"rule": "MayRunAs", "ranges": [{"min": 10000}, {"max": 99}, "5000-", "-10", "20-30"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&settings.fs_group).unwrap(),
+            serde_json::json!({
+                "rule": "MayRunAs",
+                "ranges": [
+                    {"min": 10000, "max": MAX_GID},
+                    {"min": MIN_GID, "max": 99},
+                    {"min": 5000, "max": MAX_GID},
+                    {"min": MIN_GID, "max": 10},
+                    {"min": 20, "max": 30},
+                ],
+            })
+        );
+
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"rule": "MayRunAs", "ranges": [{"min": 3000, "max": 2000}]}"#
+            )
+            .unwrap()
+            .validate(),
+            Err("ranges[0].min: 3000 cannot be greater than max 2000".to_string())
+        );
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MayRunAs", "ranges": [{"max": -1}]}"#)
+                .unwrap()
+                .validate(),
+            Err(
+                "ranges[0].max: -1 is not a valid GID, it must be between 0 and 2147483647"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            Settings::from_slice(br#"{"rule": "MayRunAs", "ranges": ["-"]}"#).unwrap_err(),
+            "ranges[0]: a range must set min, max, or both at line 1 column 35"
+        );
+    }
+
+    #[test]
+    fn must_run_as_requires_the_min_of_the_default_range() {
+        let validate = |settings: &str| {
+            Settings::from_slice(settings.as_bytes())
+                .unwrap()
+                .validate()
+        };
+
+        let error = Err(
+            "ranges[0].min: is required on the first range of MustRunAs, which is the default fsGroup"
+                .to_string(),
+        );
+        assert_eq!(
+            validate(r#"{"rule": "MustRunAs", "ranges": [{"max": 999}]}"#),
+            error
+        );
+        assert_eq!(
+            validate(r#"{"rule": "MustRunAs", "ranges": ["-999", "2000-3000"]}"#),
+            error
+        );
+        assert_eq!(
+            validate(
+                r#"{"apiVersion": "v2", "fsGroup": {"rule": "MustRunAs", "ranges": [{"max": 999}]}}"#
+            ),
+            Err(
+                "fsGroup.ranges[0].min: is required on the first range of MustRunAs, which is the default fsGroup"
+                    .to_string()
+            )
+        );
+        // Only the first range is the default, and only MustRunAs defaults it
+        assert_eq!(
+            validate(r#"{"rule": "MustRunAs", "ranges": ["2000-3000", {"max": 999}]}"#),
+            Ok(())
+        );
+        assert_eq!(
+            validate(r#"{"rule": "MustRunAs", "ranges": [{"min": 0, "max": 999}]}"#),
+            Ok(())
+        );
+        assert_eq!(
+            validate(r#"{"rule": "MayRunAs", "ranges": [{"max": 999}]}"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn must_not_run_as_settings() {
+        assert_eq!(
+            Settings {
+                fs_group: Rule::MustNotRunAs(Ranges::new(Vec::new())),
+                ..Settings::default()
+            }
+            .validate(),
+            Err("ranges: MustNotRunAs must contain at least one range".to_string())
+        );
+
+        let settings = Settings::from_slice(
+            br#"{"rule": "MustNotRunAs", "ranges": ["0-99"], "values": [65534]}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            serde_json::to_value(&settings.fs_group).unwrap(),
+            serde_json::json!({
+                "rule": "MustNotRunAs",
+                "ranges": [{"min": 0, "max": 99}, {"min": 65534, "max": 65534}],
+            })
+        );
+    }
+
+    #[test]
+    fn v1_settings_are_upgraded_to_v2() {
+        let v1 = br#"{
+            "rule": "MustRunAs",
+            "ranges": ["1000-1999"],
+            "values": [3000],
+            "profiles": [{"subjects": {"users": ["alice"]}, "rule": "MayRunAs", "ranges": [{"min": 5000}]}],
+            "exemptPodSelector": {"matchLabels": {"app.kubernetes.io/part-of": "storage"}},
+            "exemptImages": ["registry.example.com/storage/*"],
+            "mirrorPods": "Warn",
+            "strictRanges": true
+        }"#;
+        let v2 = serde_json::json!({
+            "apiVersion": "v2",
+            "fsGroup": {
+                "rule": "MustRunAs",
+                "ranges": [{"min": 1000, "max": 1999}, {"min": 3000, "max": 3000}],
+            },
+            "profiles": [{
+                "subjects": {"users": ["alice"]},
+                "fsGroup": {"rule": "MayRunAs", "ranges": [{"min": 5000, "max": MAX_GID}]},
+            }],
+            "exemptions": {
+                "podSelector": {"matchLabels": {"app.kubernetes.io/part-of": "storage"}},
+                "images": ["registry.example.com/storage/*"],
+            },
+            "mirrorPods": "Warn",
+            "strictRanges": true,
+        });
+
+        let settings = Settings::from_slice(v1).unwrap();
+        assert_eq!(settings.api_version, ApiVersion::V1);
+        assert_eq!(serde_json::to_value(&settings).unwrap(), v2);
+
+        let settings = Settings::from_slice(v2.to_string().as_bytes()).unwrap();
+        assert_eq!(settings.api_version, ApiVersion::V2);
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(serde_json::to_value(&settings).unwrap(), v2);
+    }
+
+    #[test]
+    fn settings_fields_must_match_the_api_version() {
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"apiVersion": "v2", "rule": "RunAsAny", "exemptImages": ["nginx"]}"#
+            )
+            .unwrap_err(),
+            "rule: is not supported by apiVersion v2, set it under fsGroup; fsGroup: is required; \
+             exemptImages: is not supported by apiVersion v2, set it under exemptions"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"fsGroup": {"rule": "RunAsAny"}, "profiles": [{"podSelector": {}, "fsGroup": {"rule": "RunAsAny"}}]}"#
+            )
+            .unwrap_err(),
+            "fsGroup: requires apiVersion v2; rule: is required; \
+             profiles[0].fsGroup: requires apiVersion v2; profiles[0].rule: is required"
+        );
+        assert_eq!(
+            Settings::from_slice(br#"{"apiVersion": "v3", "rule": "RunAsAny"}"#).unwrap_err(),
+            "apiVersion: unknown variant `v3`, expected `v1` or `v2` at line 1 column 19"
+        );
+        assert_eq!(
+            Settings::from_slice(
+                br#"{"apiVersion": "v2", "fsGroup": {"rule": "MustRunAs", "range": []}}"#
+            )
+            .unwrap_err(),
+            "fsGroup.range: unknown field `range`, expected one of `rule`, `ranges`, `values` at line 1 column 61"
+        );
+    }
+
+    #[test]
+    fn v2_errors_carry_the_v2_path() {
+        let settings = Settings::from_slice(
+            br#"{
+                "apiVersion": "v2",
+                "fsGroup": {"rule": "MustRunAs", "ranges": ["2000-1000", "10-20", "15-30"]},
+                "profiles": [{"podSelector": {}, "fsGroup": {"rule": "MayRunAs"}}],
+                "exemptions": {"podSelector": {"matchExpressions": [{"key": "tier", "operator": "In"}]}},
+                "strictRanges": true
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            settings.validate(),
+            Err([
+                "fsGroup.ranges[0].min: 2000 cannot be greater than max 1000",
+                "exemptions.podSelector.matchExpressions[0].values: operator In requires at least one value",
+                "profiles[0].fsGroup.ranges: MayRunAs must contain at least one range",
+                "fsGroup.ranges[2]: overlaps ranges[1]",
+            ]
+            .join("; "))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "schema")]
+    fn settings_schema_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/settings-schema.json");
+        let schema = serde_json::to_string_pretty(&schemars::schema_for!(Settings)).unwrap() + "\n";
+        if std::env::var_os("UPDATE_SETTINGS_SCHEMA").is_some() {
+            std::fs::write(path, &schema).unwrap();
+        }
+
+        assert!(
+            std::fs::read_to_string(path).unwrap_or_default() == schema,
+            "settings-schema.json is out of date, run `make settings-schema` to update it"
+        );
     }
 }
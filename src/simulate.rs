@@ -0,0 +1,428 @@
+//! Runs the workloads of a cluster dump, like the output of `kubectl get
+//! pods,deployments -A -o json`, through the policy, to find out which of
+//! them would be rejected or mutated by the given settings.
+
+use crate::{
+    cli::admission_request,
+    evaluate,
+    settings::{Range, Ranges, Settings},
+    PolicyResponse,
+};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Outcome of the simulation in a namespace
+#[derive(Serialize, Default, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NamespaceReport {
+    pub namespace: String,
+    pub accepted: usize,
+    pub mutated: usize,
+    pub rejected: usize,
+    /// fsGroups of the rejected workloads
+    pub offending_fs_groups: Vec<i64>,
+    /// Smallest set of ranges including every fsGroup set by the workloads
+    /// of the namespace
+    pub suggested_ranges: Vec<Range>,
+}
+
+/// Workloads whose pods are evaluated, along with the path of their pod
+/// template
+const WORKLOADS: &[(&str, &[&str])] = &[
+    ("Deployment", &["spec", "template"]),
+    ("ReplicaSet", &["spec", "template"]),
+    ("ReplicationController", &["spec", "template"]),
+    ("StatefulSet", &["spec", "template"]),
+    ("DaemonSet", &["spec", "template"]),
+    ("Job", &["spec", "template"]),
+    ("CronJob", &["spec", "jobTemplate", "spec", "template"]),
+];
+
+/// Flattens `List` documents into their items
+pub(crate) fn items(documents: Vec<Value>) -> Vec<Value> {
+    documents
+        .into_iter()
+        .flat_map(|document| match document["items"].as_array() {
+            Some(items) if is_list(&document) => items.clone(),
+            _ => vec![document],
+        })
+        .collect()
+}
+
+fn is_list(document: &Value) -> bool {
+    document["kind"]
+        .as_str()
+        .is_some_and(|kind| kind.ends_with("List"))
+}
+
+/// Pod that the item is, or that its controller would create. Items that
+/// are neither pods nor workloads have none.
+pub(crate) fn workload_pod(item: &Value) -> Option<Value> {
+    let kind = item["kind"].as_str()?;
+    if kind == "Pod" {
+        return Some(item.clone());
+    }
+    let (_, path) = WORKLOADS.iter().find(|(workload, _)| *workload == kind)?;
+    let template = path.iter().fold(item, |value, field| &value[*field]);
+    let mut metadata = template["metadata"].clone();
+    if !metadata.is_object() {
+        metadata = Value::Object(Default::default());
+    }
+    metadata["name"] = item["metadata"]["name"].clone();
+    metadata["namespace"] = item["metadata"]["namespace"].clone();
+    Some(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": metadata,
+        "spec": template["spec"],
+    }))
+}
+
+/// Identifies an object of the dump, to find the items managed by another
+/// one
+fn object_key(namespace: &str, kind: &str, name: &str) -> String {
+    format!("{namespace}/{kind}/{name}")
+}
+
+fn namespace(item: &Value) -> &str {
+    item["metadata"]["namespace"].as_str().unwrap_or("default")
+}
+
+/// Controllers created by another controller of the dump, along with the kind
+/// of the latter, which they are named after, followed by a generated suffix
+const PARENT_CONTROLLERS: &[(&str, &str)] = &[("ReplicaSet", "Deployment"), ("Job", "CronJob")];
+
+/// Items managed by a controller that is part of the dump, like the pods of
+/// a ReplicaSet, are evaluated through their controller only. The owner
+/// chain is followed through the name of the owner when it is missing from
+/// the dump, as for the pods of the ReplicaSet of a Deployment.
+fn has_controller_in(item: &Value, keys: &HashSet<String>) -> bool {
+    let namespace = namespace(item);
+    let contains = |kind: &str, name: &str| keys.contains(&object_key(namespace, kind, name));
+    item["metadata"]["ownerReferences"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|owner| owner["controller"].as_bool().unwrap_or_default())
+        .any(|owner| {
+            let kind = owner["kind"].as_str().unwrap_or_default();
+            let name = owner["name"].as_str().unwrap_or_default();
+            contains(kind, name)
+                || parent_controller(kind, name)
+                    .is_some_and(|(parent_kind, parent_name)| contains(parent_kind, parent_name))
+        })
+}
+
+/// Controller that created the given one, like the Deployment of a
+/// ReplicaSet, out of the name of the latter
+fn parent_controller<'a>(kind: &str, name: &'a str) -> Option<(&'static str, &'a str)> {
+    let (_, parent_kind) = PARENT_CONTROLLERS
+        .iter()
+        .find(|(controller_kind, _)| *controller_kind == kind)?;
+    let (parent_name, _) = name.rsplit_once('-')?;
+    Some((parent_kind, parent_name))
+}
+
+/// Evaluates every pod and workload of the items, as if their pods were
+/// being created, and summarizes the outcome per namespace
+pub(crate) fn simulate(items: &[Value], settings: &Settings) -> Result<Vec<NamespaceReport>> {
+    let keys: HashSet<String> = items
+        .iter()
+        .map(|item| {
+            object_key(
+                namespace(item),
+                item["kind"].as_str().unwrap_or_default(),
+                item["metadata"]["name"].as_str().unwrap_or_default(),
+            )
+        })
+        .collect();
+
+    let mut reports: BTreeMap<String, NamespaceReport> = BTreeMap::new();
+    let mut fs_groups: BTreeMap<String, BTreeSet<i64>> = BTreeMap::new();
+    for item in items.iter().filter(|item| !has_controller_in(item, &keys)) {
+        let Some(pod) = workload_pod(item) else {
+            continue;
+        };
+        let namespace = namespace(item).to_string();
+        let report = reports
+            .entry(namespace.clone())
+            .or_insert_with(|| NamespaceReport {
+                namespace: namespace.clone(),
+                ..NamespaceReport::default()
+            });
+        let fs_group = pod["spec"]["securityContext"]["fsGroup"].as_i64();
+        if let Some(fs_group) = fs_group {
+            fs_groups.entry(namespace).or_default().insert(fs_group);
+        }
+
+        match evaluate(&admission_request(&pod)?, settings.clone())? {
+            PolicyResponse::Accept | PolicyResponse::AcceptWithWarnings(_) => report.accepted += 1,
+            PolicyResponse::Mutate(_) | PolicyResponse::MutateWithWarnings(..) => {
+                report.mutated += 1
+            }
+            PolicyResponse::Reject(_) => {
+                report.rejected += 1;
+                report.offending_fs_groups.extend(fs_group);
+            }
+        }
+    }
+
+    Ok(reports
+        .into_values()
+        .map(|mut report| {
+            report.offending_fs_groups.sort_unstable();
+            report.offending_fs_groups.dedup();
+            report.suggested_ranges = covering_ranges(
+                fs_groups
+                    .get(&report.namespace)
+                    .into_iter()
+                    .flatten()
+                    .copied(),
+            );
+            report
+        })
+        .collect())
+}
+
+/// Smallest set of ranges including all the fsGroups
+pub(crate) fn covering_ranges(fs_groups: impl IntoIterator<Item = i64>) -> Vec<Range> {
+    Ranges::new(
+        fs_groups
+            .into_iter()
+            .map(|fs_group| Range {
+                min: fs_group,
+                max: fs_group,
+            })
+            .collect(),
+    )
+    .normalized()
+    .to_vec()
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+const COLUMNS: [&str; 6] = [
+    "NAMESPACE",
+    "ACCEPTED",
+    "MUTATED",
+    "REJECTED",
+    "OFFENDING FSGROUPS",
+    "SUGGESTED RANGES",
+];
+
+fn rows(reports: &[NamespaceReport]) -> Vec<[String; 6]> {
+    reports
+        .iter()
+        .map(|report| {
+            [
+                report.namespace.clone(),
+                report.accepted.to_string(),
+                report.mutated.to_string(),
+                report.rejected.to_string(),
+                join(&report.offending_fs_groups),
+                join(&report.suggested_ranges),
+            ]
+        })
+        .collect()
+}
+
+/// Formats the reports as a table, with aligned columns
+pub(crate) fn to_table(reports: &[NamespaceReport]) -> String {
+    let header = COLUMNS.map(str::to_string);
+    let rows = rows(reports);
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("   ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Formats the reports as CSV, listing the fsGroups and the ranges separated
+/// by spaces
+pub(crate) fn to_csv(reports: &[NamespaceReport]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let header = COLUMNS.map(|column| column.to_lowercase().replace(' ', "_"));
+    std::iter::once(header)
+        .chain(rows(reports))
+        .map(|row| {
+            row.iter()
+                .map(|value| field(value))
+                .collect::<Vec<_>>()
+                .join(",")
+                + "\n"
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump() -> Vec<Value> {
+        let pod_spec = |fs_group: Option<i64>| {
+            let mut spec = serde_json::json!({"containers": [{"name": "app", "image": "app"}]});
+            if let Some(fs_group) = fs_group {
+                spec["securityContext"] = serde_json::json!({ "fsGroup": fs_group });
+            }
+            spec
+        };
+        items(vec![serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "items": [
+                {
+                    "apiVersion": "apps/v1",
+                    "kind": "Deployment",
+                    "metadata": {"name": "web", "namespace": "shop"},
+                    "spec": {"template": {"metadata": {"labels": {"app": "web"}}, "spec": pod_spec(Some(1000))}},
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "Pod",
+                    "metadata": {
+                        "name": "web-5d8f7-x2x9z",
+                        "namespace": "shop",
+                        "ownerReferences": [{"kind": "ReplicaSet", "name": "web-5d8f7", "controller": true}],
+                    },
+                    "spec": pod_spec(Some(1000)),
+                },
+                {
+                    "apiVersion": "batch/v1",
+                    "kind": "CronJob",
+                    "metadata": {"name": "backup", "namespace": "shop"},
+                    "spec": {"jobTemplate": {"spec": {"template": {"spec": pod_spec(Some(0))}}}},
+                },
+                {
+                    "apiVersion": "batch/v1",
+                    "kind": "Job",
+                    "metadata": {
+                        "name": "backup-28934520",
+                        "namespace": "shop",
+                        "ownerReferences": [{"kind": "CronJob", "name": "backup", "controller": true}],
+                    },
+                    "spec": {"template": {"spec": pod_spec(Some(0))}},
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "Pod",
+                    "metadata": {
+                        "name": "backup-28934520-k7d2p",
+                        "namespace": "shop",
+                        "ownerReferences": [{"kind": "Job", "name": "backup-28934520", "controller": true}],
+                    },
+                    "spec": pod_spec(Some(0)),
+                },
+                {
+                    "apiVersion": "apps/v1",
+                    "kind": "StatefulSet",
+                    "metadata": {"name": "db", "namespace": "shop"},
+                    "spec": {"template": {"spec": pod_spec(Some(1001))}},
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "Pod",
+                    "metadata": {"name": "debug", "namespace": "tools"},
+                    "spec": pod_spec(None),
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "Service",
+                    "metadata": {"name": "web", "namespace": "shop"},
+                },
+            ],
+        })])
+    }
+
+    fn reports() -> Vec<NamespaceReport> {
+        let settings =
+            Settings::from_slice(br#"{"rule": "MustRunAs", "ranges": ["1000-1000"]}"#).unwrap();
+        simulate(&dump(), &settings).unwrap()
+    }
+
+    #[test]
+    fn simulate_cluster_dump() {
+        assert_eq!(
+            reports(),
+            vec![
+                NamespaceReport {
+                    namespace: "shop".to_string(),
+                    accepted: 1,
+                    mutated: 0,
+                    rejected: 2,
+                    offending_fs_groups: vec![0, 1001],
+                    suggested_ranges: vec![
+                        Range { min: 0, max: 0 },
+                        Range {
+                            min: 1000,
+                            max: 1001
+                        }
+                    ],
+                },
+                NamespaceReport {
+                    namespace: "tools".to_string(),
+                    accepted: 0,
+                    mutated: 1,
+                    rejected: 0,
+                    offending_fs_groups: Vec::new(),
+                    suggested_ranges: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_formats() {
+        let reports = reports();
+        assert_eq!(
+            to_table(&reports),
+            "NAMESPACE   ACCEPTED   MUTATED   REJECTED   OFFENDING FSGROUPS   SUGGESTED RANGES\n\
+             shop        1          0         2          0 1001               0 1000-1001\n\
+             tools       0          1         0\n"
+        );
+        assert_eq!(
+            to_csv(&reports),
+            "namespace,accepted,mutated,rejected,offending_fsgroups,suggested_ranges\n\
+             shop,1,0,2,0 1001,0 1000-1001\n\
+             tools,0,1,0,,\n"
+        );
+        assert_eq!(
+            serde_json::to_value(&reports[0]).unwrap(),
+            serde_json::json!({
+                "namespace": "shop",
+                "accepted": 1,
+                "mutated": 0,
+                "rejected": 2,
+                "offendingFsGroups": [0, 1001],
+                "suggestedRanges": [{"min": 0, "max": 0}, {"min": 1000, "max": 1001}],
+            })
+        );
+    }
+}
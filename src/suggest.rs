@@ -0,0 +1,194 @@
+//! Infers settings admitting every workload of a cluster dump, as a starting
+//! point when moving workloads under the policy.
+
+use crate::{
+    settings::{ApiVersion, Profile, Range, Ranges, Rule, Settings, Subjects},
+    simulate::{covering_ranges, workload_pod},
+};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// fsGroups observed in each namespace, and in the whole cluster under `None`
+type FsGroups = BTreeMap<Option<String>, BTreeSet<i64>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Scope {
+    /// One set of ranges for the whole cluster
+    Global,
+    /// One profile per namespace, matching the service accounts of the
+    /// namespace, on top of the global ranges
+    Namespace,
+}
+
+/// Suggests `MayRunAs` settings whose ranges include every fsGroup set by
+/// the pods and workloads of the items. Ranges separated by at most `gap`
+/// GIDs are merged together, trading precision for shorter settings.
+pub(crate) fn suggest(items: &[Value], scope: Scope, gap: i64) -> Result<Settings> {
+    let mut fs_groups = FsGroups::new();
+    for item in items {
+        let Some(pod) = workload_pod(item) else {
+            continue;
+        };
+        let Some(fs_group) = pod["spec"]["securityContext"]["fsGroup"].as_i64() else {
+            continue;
+        };
+        fs_groups.entry(None).or_default().insert(fs_group);
+        if scope == Scope::Namespace {
+            let namespace = item["metadata"]["namespace"].as_str().unwrap_or("default");
+            fs_groups
+                .entry(Some(namespace.to_string()))
+                .or_default()
+                .insert(fs_group);
+        }
+    }
+
+    let ranges = |fs_groups: &BTreeSet<i64>| {
+        Ranges::new(merge_gaps(covering_ranges(fs_groups.iter().copied()), gap))
+    };
+    let global = fs_groups
+        .remove(&None)
+        .ok_or_else(|| anyhow!("no pod or workload sets an fsGroup"))?;
+    Ok(Settings {
+        api_version: ApiVersion::V2,
+        fs_group: Rule::MayRunAs(ranges(&global)),
+        profiles: fs_groups
+            .iter()
+            .map(|(namespace, fs_groups)| Profile {
+                subjects: Subjects {
+                    pod_service_accounts: vec![format!(
+                        "{}/*",
+                        namespace.as_deref().unwrap_or_default()
+                    )],
+                    ..Subjects::default()
+                },
+                pod_selector: None,
+                pod_annotation_selector: None,
+                fs_group: Rule::MayRunAs(ranges(fs_groups)),
+            })
+            .collect(),
+        ..Settings::default()
+    })
+}
+
+/// Merges the sorted ranges separated by at most `gap` GIDs
+fn merge_gaps(ranges: Vec<Range>, gap: i64) -> Vec<Range> {
+    let mut merged: Vec<Range> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.min - last.max - 1 <= gap => last.max = range.max,
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kubewarden::settings::Validatable;
+
+    fn pod(namespace: &str, fs_group: Option<i64>) -> Value {
+        let mut pod = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": "app", "namespace": namespace},
+            "spec": {"containers": [{"name": "app", "image": "app"}]},
+        });
+        if let Some(fs_group) = fs_group {
+            pod["spec"]["securityContext"] = serde_json::json!({ "fsGroup": fs_group });
+        }
+        pod
+    }
+
+    fn items() -> Vec<Value> {
+        vec![
+            pod("shop", Some(1000)),
+            pod("shop", Some(1001)),
+            pod("shop", Some(1010)),
+            pod("tools", Some(2000)),
+            pod("tools", None),
+            serde_json::json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"name": "db", "namespace": "shop"},
+                "spec": {"template": {"spec": {"securityContext": {"fsGroup": 5000}}}},
+            }),
+        ]
+    }
+
+    #[test]
+    fn suggest_global_ranges() -> Result<()> {
+        assert_eq!(
+            serde_json::to_value(suggest(&items(), Scope::Global, 0)?)?,
+            serde_json::json!({
+                "apiVersion": "v2",
+                "fsGroup": {
+                    "rule": "MayRunAs",
+                    "ranges": [
+                        {"min": 1000, "max": 1001},
+                        {"min": 1010, "max": 1010},
+                        {"min": 2000, "max": 2000},
+                        {"min": 5000, "max": 5000},
+                    ],
+                },
+                "mirrorPods": "Validate",
+            })
+        );
+
+        assert_eq!(
+            serde_json::to_value(suggest(&items(), Scope::Global, 10)?.fs_group)?,
+            serde_json::json!({
+                "rule": "MayRunAs",
+                "ranges": [
+                    {"min": 1000, "max": 1010},
+                    {"min": 2000, "max": 2000},
+                    {"min": 5000, "max": 5000},
+                ],
+            })
+        );
+
+        assert_eq!(
+            suggest(&[pod("shop", None)], Scope::Global, 0)
+                .unwrap_err()
+                .to_string(),
+            "no pod or workload sets an fsGroup"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_ranges_per_namespace() -> Result<()> {
+        let settings = suggest(&items(), Scope::Namespace, 1000)?;
+
+        assert_eq!(
+            serde_json::to_value(&settings)?,
+            serde_json::json!({
+                "apiVersion": "v2",
+                "fsGroup": {
+                    "rule": "MayRunAs",
+                    "ranges": [{"min": 1000, "max": 2000}, {"min": 5000, "max": 5000}],
+                },
+                "profiles": [
+                    {
+                        "subjects": {"podServiceAccounts": ["shop/*"]},
+                        "fsGroup": {
+                            "rule": "MayRunAs",
+                            "ranges": [{"min": 1000, "max": 1010}, {"min": 5000, "max": 5000}],
+                        },
+                    },
+                    {
+                        "subjects": {"podServiceAccounts": ["tools/*"]},
+                        "fsGroup": {"rule": "MayRunAs", "ranges": [{"min": 2000, "max": 2000}]},
+                    },
+                ],
+                "mirrorPods": "Validate",
+            })
+        );
+
+        let settings = Settings::from_slice(&serde_json::to_vec(&settings)?).unwrap();
+        assert_eq!(settings.validate(), Ok(()));
+        Ok(())
+    }
+}